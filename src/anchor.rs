@@ -0,0 +1,32 @@
+use bevy::prelude::*;
+
+/// Query for the local transforms of entities, and their parents to find their place in the world
+pub(crate) type Hierarchy<'w, 's, 'a> = Query<'w, 's, (&'a Transform, Option<&'a Parent>)>;
+
+/// Transform from the space of the path into the space of the entity's *parent*, if the path is
+/// relative to an *anchor*
+///
+/// Built from the current transforms rather than the global ones, so anchors moved earlier in the
+/// frame carry their followers along in the same frame.
+pub(crate) fn path_space(
+    anchor: Entity,
+    parent: Option<Entity>,
+    hierarchy: &Hierarchy,
+) -> Option<Mat4> {
+    let anchor = world_matrix(anchor, hierarchy)?;
+    let parent = parent
+        .and_then(|parent| world_matrix(parent, hierarchy))
+        .unwrap_or(Mat4::IDENTITY);
+    Some(parent.inverse() * anchor)
+}
+
+/// Transform from the space of *entity* into the world, combining the transforms of its ancestors
+fn world_matrix(entity: Entity, hierarchy: &Hierarchy) -> Option<Mat4> {
+    let (transform, mut parent) = hierarchy.get(entity).ok()?;
+    let mut matrix = transform.compute_matrix();
+    while let Some((transform, next)) = parent.and_then(|parent| hierarchy.get(parent.get()).ok()) {
+        matrix = transform.compute_matrix() * matrix;
+        parent = next;
+    }
+    Some(matrix)
+}
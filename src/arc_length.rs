@@ -0,0 +1,223 @@
+/// Position on a path, given as a fraction along the segment between two points
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SegmentLocation {
+    /// Index of the point the segment starts at
+    pub start: usize,
+    /// Index of the point the segment ends at
+    pub end: usize,
+    /// How far along the segment the position lies, from 0 to 1
+    pub fraction: f32,
+}
+
+/// Cumulative distances along the points of a path
+#[derive(Default, Clone, Debug)]
+pub(crate) struct ArcLengths {
+    /// Distance from the first point to each point
+    cumulative: Vec<f32>,
+    /// Distance from the last point back to the first one
+    closing: f32,
+}
+
+impl ArcLengths {
+    /// Build the table from the points of a path, using *distance* to measure between two points
+    pub(crate) fn new<T>(points: &[T], distance: impl Fn(&T, &T) -> f32) -> Self {
+        let mut cumulative = Vec::with_capacity(points.len());
+        let mut total = 0.;
+        for (index, point) in points.iter().enumerate() {
+            if index > 0 {
+                total += distance(&points[index - 1], point);
+            }
+            cumulative.push(total);
+        }
+        let closing = match (points.first(), points.last()) {
+            (Some(first), Some(last)) => distance(last, first),
+            _ => 0.,
+        };
+        Self {
+            cumulative,
+            closing,
+        }
+    }
+
+    /// Total length of the path
+    pub(crate) fn length(&self, is_loop: bool) -> f32 {
+        let open = self.cumulative.last().copied().unwrap_or(0.);
+        if is_loop {
+            open + self.closing
+        } else {
+            open
+        }
+    }
+
+    /// Distance from the first point to the point at *index*
+    pub(crate) fn distance_to(&self, index: usize) -> f32 {
+        self.cumulative[index]
+    }
+
+    /// Find which of the points at *indices*, sorted along the path, are passed when moving from
+    /// *from* to *to*
+    ///
+    /// Returns positions within *indices* together with the unwrapped distance at which they are
+    /// passed, in the order the points are passed. Moving along a looping path may pass the same
+    /// point several times.
+    pub(crate) fn passed(
+        &self,
+        indices: &[usize],
+        from: f32,
+        to: f32,
+        is_loop: bool,
+    ) -> Vec<(usize, f32)> {
+        let mut passed = Vec::new();
+        if from == to {
+            return passed;
+        }
+        let length = self.length(is_loop);
+        let (first_lap, last_lap) = if is_loop && length > 0. {
+            ((from / length).floor() as i64, (to / length).floor() as i64)
+        } else {
+            (0, 0)
+        };
+        let at = |base: f32, position: usize| base + self.cumulative[indices[position]];
+
+        if to > from {
+            for lap in first_lap..=last_lap {
+                let base = lap as f32 * length;
+                let start = indices.partition_point(|index| base + self.cumulative[*index] <= from);
+                let end = indices.partition_point(|index| base + self.cumulative[*index] <= to);
+                passed.extend((start..end).map(|position| (position, at(base, position))));
+            }
+        } else {
+            for lap in (last_lap..=first_lap).rev() {
+                let base = lap as f32 * length;
+                let start = indices.partition_point(|index| base + self.cumulative[*index] < to);
+                let end = indices.partition_point(|index| base + self.cumulative[*index] < from);
+                passed.extend(
+                    (start..end)
+                        .rev()
+                        .map(|position| (position, at(base, position))),
+                );
+            }
+        }
+        passed
+    }
+
+    /// Length of the segment that starts at the point at *index*
+    pub(crate) fn segment_length(&self, index: usize) -> f32 {
+        match self.cumulative.get(index + 1) {
+            Some(next) => next - self.cumulative[index],
+            None => self.closing,
+        }
+    }
+
+    /// Wraps *distance* around looping paths and clamps it to the ends of open paths
+    pub(crate) fn normalize(&self, distance: f32, is_loop: bool) -> f32 {
+        let length = self.length(is_loop);
+        if is_loop && length > 0. {
+            distance.rem_euclid(length)
+        } else {
+            distance.clamp(0., length)
+        }
+    }
+
+    /// Find the segment that lies at *distance* from the first point
+    pub(crate) fn locate(&self, distance: f32, is_loop: bool) -> Option<SegmentLocation> {
+        let last = self.cumulative.len().checked_sub(1)?;
+        let distance = self.normalize(distance, is_loop);
+        let start = self
+            .cumulative
+            .partition_point(|d| *d <= distance)
+            .saturating_sub(1);
+
+        let (start, end, length) = if start < last {
+            (
+                start,
+                start + 1,
+                self.cumulative[start + 1] - self.cumulative[start],
+            )
+        } else if is_loop {
+            (last, 0, self.closing)
+        } else {
+            // The end of an open path lies at the end of its last segment
+            let start = last.saturating_sub(1);
+            (start, last, self.cumulative[last] - self.cumulative[start])
+        };
+        let fraction = if length > 0. {
+            ((distance - self.cumulative[start]) / length).clamp(0., 1.)
+        } else {
+            0.
+        };
+        Some(SegmentLocation {
+            start,
+            end,
+            fraction,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Points at 0, 10 and 30 along a line, which is 30 long open and 60 long closed
+    fn lengths() -> ArcLengths {
+        ArcLengths::new(&[0., 10., 30.], |a: &f32, b: &f32| (a - b).abs())
+    }
+
+    fn location(start: usize, end: usize, fraction: f32) -> Option<SegmentLocation> {
+        Some(SegmentLocation {
+            start,
+            end,
+            fraction,
+        })
+    }
+
+    #[test]
+    fn locate_on_open_paths_clamps_to_the_ends() {
+        let lengths = lengths();
+        assert_eq!(lengths.locate(5., false), location(0, 1, 0.5));
+        assert_eq!(lengths.locate(20., false), location(1, 2, 0.5));
+        assert_eq!(lengths.locate(-5., false), location(0, 1, 0.));
+        assert_eq!(lengths.locate(40., false), location(1, 2, 1.));
+    }
+
+    #[test]
+    fn locate_on_loops_wraps_around() {
+        let lengths = lengths();
+        assert_eq!(lengths.locate(45., true), location(2, 0, 0.5));
+        assert_eq!(lengths.locate(65., true), location(0, 1, 0.5));
+        assert_eq!(lengths.locate(-15., true), location(2, 0, 0.5));
+        assert_eq!(
+            ArcLengths::new::<f32>(&[], |_, _| 0.).locate(0., true),
+            None
+        );
+    }
+
+    #[test]
+    fn passed_on_open_paths() {
+        let lengths = lengths();
+        assert_eq!(
+            lengths.passed(&[0, 1, 2], 0., 30., false),
+            [(1, 10.), (2, 30.)]
+        );
+        assert_eq!(
+            lengths.passed(&[0, 1, 2], 30., 0., false),
+            [(1, 10.), (0, 0.)]
+        );
+        assert_eq!(lengths.passed(&[1, 2], 0., 5., false), []);
+        assert_eq!(lengths.passed(&[0, 1, 2], 10., 10., false), []);
+    }
+
+    #[test]
+    fn passed_on_loops_counts_every_lap() {
+        let lengths = lengths();
+        assert_eq!(
+            lengths.passed(&[0, 2], 50., 150., true),
+            [(0, 60.), (1, 90.), (0, 120.), (1, 150.)]
+        );
+        assert_eq!(
+            lengths.passed(&[0, 1, 2], 5., -35., true),
+            [(0, 0.), (2, -30.)]
+        );
+        assert_eq!(lengths.passed(&[1], -70., 15., true), [(0, -50.), (0, 10.)]);
+    }
+}
@@ -1,6 +1,8 @@
 /// Module for following a preset 2D Path
 pub mod components;
+pub mod events;
 pub mod path;
 pub mod plugin;
+pub mod svg;
 pub mod systems;
 mod vec2_geo_nd;
@@ -1,15 +1,192 @@
 use crate::follow_path2::path::Path2;
+use crate::traversal::{Dwell, OnFinish, PathDirection};
 use bevy::prelude::*;
+use std::f32::consts::{PI, TAU};
+
+/// How quickly a 2D follower turns to face a new heading
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum TurnLimit {
+    /// Snap to the new heading immediately
+    #[default]
+    Instant,
+    /// Turn at no more than the given angular velocity, in radians per second
+    MaxRate(f32),
+    /// Turn exponentially towards the new heading, with the given rate per second
+    ///
+    /// Higher rates turn faster; a rate of 1 closes about 63% of the remaining angle each second.
+    Smooth(f32),
+}
+
+/// How a 2D follower is rotated as it moves along its path
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum RotationMode {
+    /// Leave the rotation alone, so other systems can control it
+    None,
+    /// Face the direction of travel
+    #[default]
+    FaceDirection,
+    /// Face the point the given distance further along the path
+    LookAhead(f32),
+    /// Keep the given angle in radians
+    Fixed(f32),
+}
 
 /// Component that follows a 2D path at a specific speed
 #[derive(Component)]
 pub struct FollowPath2 {
-    /// The [Path](Path2) to follow
-    pub path: Path2,
-    /// The index of the current point the entity is moving to
-    pub cur_target: usize,
-    /// Speed at which the entity moves
+    /// Handle to the [Path](Path2) to follow
+    pub path: Handle<Path2>,
+    /// Distance the entity has travelled along the path
+    pub distance: f32,
+    /// Length of the path when the entity last moved along it
+    ///
+    /// When the path changes length, like when its file is reloaded, the
+    /// [distance](FollowPath2::distance) is scaled along with it to keep the entity's progress.
+    /// Reset it to [None] when switching to another path to keep the distance as it is.
+    pub path_length: Option<f32>,
+    /// Speed at which the entity moves, in units per second
+    ///
+    /// Ignored while the path has [speed keys](Path2::speed_keys).
     pub speed: f32,
-    /// Sets the epsilon for detecting when a point has been reached
-    pub epsilon: f32,
+    /// Speed the entity is moving at right now, as it accelerates towards or brakes from
+    /// [speed](FollowPath2::speed)
+    pub current_speed: f32,
+    /// Maximum increase of the current speed, in units per second squared
+    pub max_acceleration: f32,
+    /// Maximum decrease of the current speed, in units per second squared
+    ///
+    /// When finite, the entity brakes ahead of the points it has to stop at, like the end of the path.
+    pub max_deceleration: f32,
+    /// Maximum acceleration towards the inside of bends, in units per second squared
+    ///
+    /// When finite, the entity slows down for sharp bends in the path. Without a
+    /// [deceleration limit](FollowPath2::max_deceleration), it brakes for them at this rate.
+    pub max_lateral_acceleration: f32,
+    /// Distance to keep to the right of the path, measured from its centreline
+    ///
+    /// Right is seen looking from the first point of the path towards its last, whichever
+    /// [direction](FollowPath2::direction) the entity moves in, matching the X axis of
+    /// [FollowPath3::offset](crate::follow_path3::components::FollowPath3::offset). Negative
+    /// distances are on the other side.
+    pub offset: f32,
+    /// Offset the entity is at right now, as it moves towards [offset](FollowPath2::offset)
+    pub current_offset: f32,
+    /// Maximum change of the current offset, in units per second
+    pub offset_speed: f32,
+    /// Direction in which the entity moves along the path
+    pub direction: PathDirection,
+    /// Whether the entity is held in place
+    ///
+    /// Paused entities still move along with their [anchor](FollowPath2::anchor).
+    pub paused: bool,
+    /// Entity the path is relative to, like a moving platform
+    ///
+    /// The path's points are in the anchor's space, and the entity follows them wherever the
+    /// anchor goes. Without an anchor, the points are in the space of the entity's parent.
+    pub anchor: Option<Entity>,
+    /// Multiplier for the time that passes for the entity
+    pub time_scale: f32,
+    /// Set while the entity waits at a waypoint
+    pub dwell: Option<Dwell>,
+    /// What to do once the end of the path has been reached
+    pub on_finish: OnFinish,
+    /// Number of cycles of the [path's mode](Path2::mode) completed so far
+    pub cycles_completed: u32,
+    /// How the entity is rotated as it moves
+    pub rotation_mode: RotationMode,
+    /// How quickly the entity turns to face its direction of travel
+    pub turn_limit: TurnLimit,
+    /// Angle of the entity's forward direction around the Z axis, in radians, measured from +X
+    ///
+    /// Use `PI / 2` for sprites facing +Y and `PI` for sprites facing -X.
+    pub forward_angle: f32,
+}
+
+impl FollowPath2 {
+    /// Create the component, starting at the first point of *path* and moving at *speed* units per second
+    pub fn new(path: Handle<Path2>, speed: f32) -> Self {
+        Self {
+            path,
+            distance: 0.,
+            path_length: None,
+            speed,
+            current_speed: 0.,
+            max_acceleration: f32::INFINITY,
+            max_deceleration: f32::INFINITY,
+            max_lateral_acceleration: f32::INFINITY,
+            offset: 0.,
+            current_offset: 0.,
+            offset_speed: f32::INFINITY,
+            direction: PathDirection::default(),
+            paused: false,
+            anchor: None,
+            time_scale: 1.,
+            dwell: None,
+            on_finish: OnFinish::default(),
+            cycles_completed: 0,
+            rotation_mode: RotationMode::default(),
+            turn_limit: TurnLimit::default(),
+            forward_angle: 0.,
+        }
+    }
+
+    /// Set the [forward angle](FollowPath2::forward_angle) from the direction of *axis*
+    pub fn set_forward_axis(&mut self, axis: Vec2) {
+        self.forward_angle = axis.y.atan2(axis.x);
+    }
+
+    /// Move the [current offset](FollowPath2::current_offset) towards the
+    /// [offset](FollowPath2::offset) over *delta* seconds
+    pub(crate) fn shift(&mut self, delta: f32) {
+        let difference = self.offset - self.current_offset;
+        if self.offset_speed.is_infinite() {
+            self.current_offset = self.offset;
+        } else {
+            let step = self.offset_speed * delta;
+            self.current_offset += difference.clamp(-step, step);
+        }
+    }
+
+    /// Turn from *current* towards *target*, both angles in radians, over *delta* seconds
+    pub(crate) fn turn(&self, current: f32, target: f32, delta: f32) -> f32 {
+        // The shortest way around, from -PI to PI
+        let difference = (target - current + PI).rem_euclid(TAU) - PI;
+        match self.turn_limit {
+            TurnLimit::Instant => target,
+            TurnLimit::MaxRate(rate) => current + difference.clamp(-rate * delta, rate * delta),
+            TurnLimit::Smooth(rate) => current + difference * (1. - (-rate * delta).exp()),
+        }
+    }
+
+    /// Turn around and move along the path in the other direction
+    pub fn reverse(&mut self) {
+        self.direction = self.direction.reversed();
+    }
+
+    /// How far along *path* the entity is, from 0 at the start to 1 at the end
+    pub fn progress(&self, path: &Path2) -> f32 {
+        let length = path.length();
+        if length > 0. {
+            (self.distance / length).clamp(0., 1.)
+        } else {
+            0.
+        }
+    }
+
+    /// Move the entity to *fraction* of the length of *path*, from 0 to 1
+    pub fn seek_fraction(&mut self, path: &Path2, fraction: f32) {
+        self.distance = fraction * path.length();
+    }
+
+    /// Number of cycles left before *path* is finished, or [None] if it never finishes
+    pub fn cycles_left(&self, path: &Path2) -> Option<u32> {
+        path.mode
+            .cycles()
+            .map(|cycles| cycles.saturating_sub(self.cycles_completed))
+    }
+
+    /// The index of the point of *path* the entity is currently moving to
+    pub fn next_point(&self, path: &Path2) -> Option<usize> {
+        path.segment_at(self.distance).map(|location| location.end)
+    }
 }
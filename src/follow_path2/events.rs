@@ -0,0 +1,24 @@
+use crate::follow_path2::path::Path2;
+use bevy::prelude::*;
+
+/// Event sent when an entity reaches the end of a [Path](Path2) that does not loop
+pub struct PathFinished {
+    /// The entity that finished the path
+    pub entity: Entity,
+    /// Handle to the [Path](Path2) that was finished
+    pub path: Handle<Path2>,
+}
+
+/// Event sent when an entity passes one of the [waypoints](Path2::waypoints) of its path
+pub struct WaypointReached {
+    /// The entity that reached the waypoint
+    pub entity: Entity,
+    /// Index of the waypoint within [Path2::waypoints]
+    pub waypoint: usize,
+    /// Index of the waypoint's point within [Path2::points]
+    pub point: usize,
+    /// Whether reaching the waypoint completed a loop around the path
+    pub completed_loop: bool,
+    /// The [tag](crate::follow_path2::path::WaypointAction::tag) of the waypoint
+    pub tag: Option<String>,
+}
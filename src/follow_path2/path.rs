@@ -1,4 +1,9 @@
 use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+
+use crate::arc_length::{ArcLengths, SegmentLocation};
+use crate::speed::{cornering_speed, interpolate_keys, resolve_keys, KeyPosition, SpeedKey};
+use crate::traversal::{self, Advance, PathDirection, PathMode};
 
 use crate::follow_path2::path::PathSegment::{CubicBezierCurve, Point, QuadraticBezierCurve};
 use crate::follow_path2::vec2_geo_nd::Vec2Geo;
@@ -8,36 +13,377 @@ use bevy::{render::mesh::PrimitiveTopology, sprite::MaterialMesh2dBundle};
 
 type Bezier2 = bezier_nd::Bezier<f32, Vec2Geo, 2>;
 
+/// Smallest cosine of half the angle of a bend that [offsets](Path2::offset_at) are scaled for,
+/// limiting how far they stick out at sharp corners
+const MIN_MITER_COSINE: f32 = 0.25;
+
 /// Possible segments to build a 2D path from
 ///
 /// The last point of the segment will always connect to the first point of the next.
-pub(crate) enum PathSegment {
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PathSegment {
+    /// A single point, connected to the previous one by a line
     Point(Vec2),
     /// Points of a cubic bezier curve, with 2 control points.
     CubicBezierCurve {
+        /// End point of the curve
         to: Vec2,
+        /// Control point near the start of the curve
         ctrl1: Vec2,
+        /// Control point near the end of the curve
         ctrl2: Vec2,
+        /// How close to straight lines the curve is split into, lower values giving more points
         straightness: f32,
     },
     /// Points of a quadratic bezier curve, with a single control point
     QuadraticBezierCurve {
+        /// End point of the curve
         to: Vec2,
+        /// Control point of the curve
         ctrl: Vec2,
+        /// How close to straight lines the curve is split into, lower values giving more points
         straightness: f32,
     },
 }
 
+/// Actions a follower carries out when it reaches a waypoint
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct WaypointAction {
+    /// Seconds to wait at the waypoint before moving on
+    pub wait: f32,
+    /// Angle around the Z axis, in radians, to face while waiting at the waypoint
+    pub facing: Option<f32>,
+    /// Custom tag that is sent along with the
+    /// [WaypointReached](crate::follow_path2::events::WaypointReached) event
+    pub tag: Option<String>,
+}
+
 /// Contains the data for the path to follow
-#[derive(Default, Clone)]
+///
+/// Paths are [assets](bevy::asset::Assets), so followers on the same route can share one.
+#[derive(Default, Clone, TypeUuid)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "Path2Data", into = "Path2Data"))]
+#[uuid = "42b41755-b892-45e4-8250-47d5c839941e"]
 pub struct Path2 {
     /// The list of [Points](bevy::math::f32::Vec2) to follow
-    pub points: Vec<Vec2>,
-    /// Whether the path circles back to the first point, or not.
-    pub is_loop: bool,
+    points: Vec<Vec2>,
+    /// Distances along the points, kept in sync with them
+    lengths: ArcLengths,
+    /// Curvature at each point, as if the path was closed
+    curvatures: Vec<f32>,
+    /// Indices of the points that are waypoints
+    waypoints: Vec<usize>,
+    /// Actions for each waypoint
+    actions: Vec<WaypointAction>,
+    /// Positions within the waypoints of those that have to be waited at, in order
+    waiting: Vec<usize>,
+    /// Keys for the speed along the path
+    speed_keys: Vec<SpeedKey>,
+    /// Distances and speeds of the speed keys, sorted along the path
+    resolved_speed_keys: Vec<(f32, f32)>,
+    /// How the path is traversed, and whether it circles back to the first point
+    pub mode: PathMode,
 }
 
 impl Path2 {
+    /// Create a path through the given [points](bevy::math::f32::Vec2)
+    pub fn new(points: Vec<Vec2>, mode: PathMode) -> Self {
+        let lengths = ArcLengths::new(&points, |a, b| a.distance(*b));
+        let curvatures = vertex_curvatures(&points);
+        let waypoints = (0..points.len()).collect();
+        let actions = vec![WaypointAction::default(); points.len()];
+        Self {
+            points,
+            lengths,
+            curvatures,
+            waypoints,
+            actions,
+            waiting: Vec::new(),
+            speed_keys: Vec::new(),
+            resolved_speed_keys: Vec::new(),
+            mode,
+        }
+    }
+
+    /// The list of [points](bevy::math::f32::Vec2) to follow
+    pub fn points(&self) -> &[Vec2] {
+        &self.points
+    }
+
+    /// Indices of the [points](Path2::points) that are waypoints
+    ///
+    /// Paths built by a [PathBuilder] only mark the ends of its segments as waypoints, while
+    /// paths created from a list of points treat every point as a waypoint.
+    pub fn waypoints(&self) -> &[usize] {
+        &self.waypoints
+    }
+
+    /// Set which [points](Path2::points) are waypoints, clearing their actions
+    ///
+    /// The waypoints are kept sorted along the path, without duplicates. Panics if an index is out
+    /// of bounds.
+    pub fn set_waypoints(&mut self, mut waypoints: Vec<usize>) {
+        assert!(
+            waypoints.iter().all(|index| *index < self.points.len()),
+            "Waypoint index out of bounds"
+        );
+        waypoints.sort_unstable();
+        waypoints.dedup();
+        self.actions = vec![WaypointAction::default(); waypoints.len()];
+        self.waiting.clear();
+        self.waypoints = waypoints;
+        self.resolved_speed_keys = resolve_keys(&self.speed_keys, &self.lengths, &self.waypoints);
+    }
+
+    /// Keys for the speed followers move at along the path
+    pub fn speed_keys(&self) -> &[SpeedKey] {
+        &self.speed_keys
+    }
+
+    /// Set the keys for the speed followers move at along the path
+    ///
+    /// While a path has speed keys, followers use them instead of their own speed. Keys
+    /// referring to waypoints that do not exist are ignored.
+    pub fn set_speed_keys(&mut self, speed_keys: Vec<SpeedKey>) {
+        self.resolved_speed_keys = resolve_keys(&speed_keys, &self.lengths, &self.waypoints);
+        self.speed_keys = speed_keys;
+    }
+
+    /// Get the speed interpolated from the [speed keys](Path2::speed_keys) at *distance*
+    ///
+    /// Returns [None] if the path has no speed keys.
+    pub fn speed_at(&self, distance: f32) -> Option<f32> {
+        let closed = self.mode.is_closed();
+        interpolate_keys(
+            &self.resolved_speed_keys,
+            self.lengths.normalize(distance, closed),
+            self.length(),
+            closed,
+        )
+    }
+
+    /// The [actions](WaypointAction) of the waypoint at *waypoint* within
+    /// [waypoints](Path2::waypoints)
+    ///
+    /// Panics if *waypoint* is out of bounds.
+    pub fn waypoint_action(&self, waypoint: usize) -> &WaypointAction {
+        &self.actions[waypoint]
+    }
+
+    /// Set the [actions](WaypointAction) of the waypoint at *waypoint* within
+    /// [waypoints](Path2::waypoints)
+    ///
+    /// Panics if *waypoint* is out of bounds.
+    pub fn set_waypoint_action(&mut self, waypoint: usize, action: WaypointAction) {
+        match (self.waiting.binary_search(&waypoint), action.wait > 0.) {
+            (Ok(position), false) => {
+                self.waiting.remove(position);
+            }
+            (Err(position), true) => self.waiting.insert(position, waypoint),
+            _ => {}
+        }
+        self.actions[waypoint] = action;
+    }
+
+    /// Move *distance* by *travel* along the path in *direction*, stopping at waypoints that have
+    /// to be waited at
+    pub(crate) fn advance(
+        &self,
+        distance: &mut f32,
+        travel: f32,
+        direction: PathDirection,
+        cycles: &mut u32,
+    ) -> Advance {
+        traversal::advance(
+            &self.lengths,
+            &self.waypoints,
+            |waypoint| self.actions[waypoint].wait,
+            self.mode,
+            distance,
+            travel,
+            direction == PathDirection::Forward,
+            cycles,
+        )
+    }
+
+    /// Distance that can be travelled from *distance* in *direction* before having to stop
+    pub(crate) fn distance_to_stop(
+        &self,
+        distance: f32,
+        direction: PathDirection,
+        cycles: u32,
+    ) -> f32 {
+        traversal::distance_to_stop(
+            &self.lengths,
+            &self.waypoints,
+            &self.waiting,
+            self.mode,
+            distance,
+            direction == PathDirection::Forward,
+            cycles,
+        )
+    }
+
+    /// Replace the points of the path, making every point a waypoint
+    pub fn set_points(&mut self, points: Vec<Vec2>) {
+        let speed_keys = std::mem::take(&mut self.speed_keys);
+        *self = Self::new(points, self.mode);
+        self.set_speed_keys(speed_keys);
+    }
+
+    /// Total length of the path, including the segment back to the first point on closed paths
+    pub fn length(&self) -> f32 {
+        self.lengths.length(self.mode.is_closed())
+    }
+
+    /// Distance along the path from the first point to the point at *index*
+    ///
+    /// Panics if *index* is out of bounds.
+    pub fn distance_to_point(&self, index: usize) -> f32 {
+        self.lengths.distance_to(index)
+    }
+
+    /// Find the segment at *distance* along the path
+    ///
+    /// Distances wrap around [closed](PathMode::is_closed) paths and are clamped to the ends of
+    /// other paths.
+    /// Returns [None] if the path has no points.
+    pub fn segment_at(&self, distance: f32) -> Option<SegmentLocation> {
+        self.lengths.locate(distance, self.mode.is_closed())
+    }
+
+    /// Get the [point](bevy::math::f32::Vec2) at *distance* along the path
+    ///
+    /// Returns [None] if the path has no points.
+    pub fn sample_at_distance(&self, distance: f32) -> Option<Vec2> {
+        self.segment_at(distance).map(|location| {
+            self.points[location.start].lerp(self.points[location.end], location.fraction)
+        })
+    }
+
+    /// Get the normalized direction of the segment at *distance* along the path
+    ///
+    /// Returns [None] if the path has no points or the segment has no length.
+    pub fn direction_at(&self, distance: f32) -> Option<Vec2> {
+        self.segment_at(distance).and_then(|location| {
+            (self.points[location.end] - self.points[location.start]).try_normalize()
+        })
+    }
+
+    /// Get the [point](bevy::math::f32::Vec2) at *distance* along the path, moved *offset* units
+    /// to the right of it, looking from its first point towards its last
+    ///
+    /// The offset path runs parallel to the segments, with its corners on the bisectors of the
+    /// bends.
+    /// Returns [None] if the path has no points.
+    pub fn offset_at(&self, distance: f32, offset: f32) -> Option<Vec2> {
+        let location = self.segment_at(distance)?;
+        let start = self.offset_point(location.start, offset);
+        let end = self.offset_point(location.end, offset);
+        Some(start.lerp(end, location.fraction))
+    }
+
+    /// The point at *index*, moved *offset* units to the right along the bisector of the segments
+    /// on either side
+    fn offset_point(&self, index: usize, offset: f32) -> Vec2 {
+        let point = self.points[index];
+        if offset == 0. {
+            return point;
+        }
+        let closed = self.mode.is_closed();
+        let count = self.points.len();
+        let segment = |start: usize| {
+            let end = (start + 1) % count;
+            (closed || end > start)
+                .then(|| (self.points[end] - self.points[start]).try_normalize())
+                .flatten()
+        };
+        let incoming = (closed || index > 0)
+            .then(|| segment((index + count - 1) % count))
+            .flatten();
+        let (direction, cosine) = match (incoming, segment(index)) {
+            (Some(incoming), Some(outgoing)) => match (incoming + outgoing).try_normalize() {
+                Some(bisector) => (bisector, bisector.dot(outgoing)),
+                None => (outgoing, 1.),
+            },
+            (Some(direction), None) | (None, Some(direction)) => (direction, 1.),
+            (None, None) => return point,
+        };
+        // Moving further out at corners keeps the offset parallel to the segments
+        let right = Vec2::new(direction.y, -direction.x);
+        point + right * offset / cosine.max(MIN_MITER_COSINE)
+    }
+
+    /// Get the curvature of the path at *distance*, interpolated between its points
+    ///
+    /// The ends of paths that are not [closed](PathMode::is_closed) are treated as straight.
+    pub fn curvature_at(&self, distance: f32) -> f32 {
+        let closed = self.mode.is_closed();
+        let last = self.points.len().saturating_sub(1);
+        let curvature = |index: usize| {
+            if !closed && (index == 0 || index == last) {
+                0.
+            } else {
+                self.curvatures[index]
+            }
+        };
+        self.segment_at(distance).map_or(0., |location| {
+            let start = curvature(location.start);
+            start + (curvature(location.end) - start) * location.fraction
+        })
+    }
+
+    /// Highest speed at *distance* from which a follower moving in *direction* can slow down in
+    /// time for the bends ahead
+    pub(crate) fn cornering_speed(
+        &self,
+        distance: f32,
+        direction: PathDirection,
+        max_lateral_acceleration: f32,
+        deceleration: f32,
+        max_speed: f32,
+    ) -> f32 {
+        cornering_speed(
+            &self.lengths,
+            &self.curvatures,
+            self.mode.is_closed(),
+            distance,
+            direction == PathDirection::Forward,
+            max_lateral_acceleration,
+            deceleration,
+            max_speed,
+        )
+    }
+
+    /// Get the [point](bevy::math::f32::Vec2) at *fraction* of the path's length, from 0 to 1
+    ///
+    /// Returns [None] if the path has no points.
+    pub fn sample_at_fraction(&self, fraction: f32) -> Option<Vec2> {
+        self.sample_at_distance(fraction * self.length())
+    }
+
+    /// Spawn a [bundle](MaterialMesh2dBundle) for drawing the path as a child of *anchor*, so it moves along
+    /// with it
+    ///
+    /// Returns the Entity for the mesh
+    #[cfg(feature = "debug_draw")]
+    pub fn spawn_anchored_mesh(
+        &self,
+        commands: &mut Commands,
+        meshes: &mut ResMut<Assets<Mesh>>,
+        materials: &mut ResMut<Assets<ColorMaterial>>,
+        color: Color,
+        anchor: Entity,
+    ) -> Entity {
+        let mesh = self.spawn_mesh(commands, meshes, materials, color);
+        commands.entity(anchor).add_child(mesh);
+        mesh
+    }
+
     /// Spawn a [bundle](MaterialMesh2dBundle) for drawing the path
     ///
     /// Returns the Entity for the mesh
@@ -51,7 +397,7 @@ impl Path2 {
     ) -> Entity {
         let mut mesh = Mesh::new(PrimitiveTopology::LineStrip);
         let mut points: Vec<[f32; 3]> = self.points.iter().map(|p| [p.x, p.y, 0.]).collect();
-        if self.is_loop {
+        if self.mode.is_closed() {
             points.push(points[0]);
         }
 
@@ -78,9 +424,93 @@ impl Path2 {
     }
 }
 
+/// Curvature at every point, as if the path was closed
+fn vertex_curvatures(points: &[Vec2]) -> Vec<f32> {
+    let count = points.len();
+    (0..count)
+        .map(|index| {
+            if count < 3 {
+                return 0.;
+            }
+            let incoming = points[index] - points[(index + count - 1) % count];
+            let outgoing = points[(index + 1) % count] - points[index];
+            // The angle turned at the point, spread over the segments on either side
+            let curvature = incoming.angle_between(outgoing).abs()
+                / ((incoming.length() + outgoing.length()) / 2.);
+            if curvature.is_finite() {
+                curvature
+            } else {
+                0.
+            }
+        })
+        .collect()
+}
+
+/// Serialized form of a [Path2], leaving out the tables derived from its points
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Path2Data {
+    points: Vec<Vec2>,
+    #[serde(default)]
+    mode: PathMode,
+    /// Every point is a waypoint if left out
+    #[serde(default)]
+    waypoints: Option<Vec<usize>>,
+    #[serde(default)]
+    actions: Vec<WaypointAction>,
+    #[serde(default)]
+    speed_keys: Vec<SpeedKey>,
+}
+
+#[cfg(feature = "serde")]
+impl From<Path2> for Path2Data {
+    fn from(path: Path2) -> Self {
+        Self {
+            points: path.points,
+            mode: path.mode,
+            waypoints: Some(path.waypoints),
+            actions: path.actions,
+            speed_keys: path.speed_keys,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<Path2Data> for Path2 {
+    type Error = String;
+
+    fn try_from(data: Path2Data) -> Result<Self, Self::Error> {
+        let mut path = Path2::new(data.points, data.mode);
+        if let Some(waypoints) = data.waypoints {
+            if waypoints.iter().any(|index| *index >= path.points.len()) {
+                return Err("Waypoint index out of bounds".to_string());
+            }
+            path.set_waypoints(waypoints);
+        }
+        if data.actions.len() > path.waypoints.len() {
+            return Err("More actions than waypoints".to_string());
+        }
+        for (waypoint, action) in data.actions.into_iter().enumerate() {
+            path.set_waypoint_action(waypoint, action);
+        }
+        path.set_speed_keys(data.speed_keys);
+        Ok(path)
+    }
+}
+
 /// Builder to simplify making [paths](Path2) using segments that are connected to each other
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(try_from = "PathBuilderData", into = "PathBuilderData")
+)]
 pub struct PathBuilder {
     segments: Vec<PathSegment>,
+    /// Actions for the waypoint at the end of each segment
+    actions: Vec<WaypointAction>,
+    /// Keys for the speed along the path
+    speed_keys: Vec<SpeedKey>,
 }
 
 impl PathBuilder {
@@ -88,12 +518,52 @@ impl PathBuilder {
     pub fn new(start: Vec2) -> Self {
         Self {
             segments: vec![Point(start)],
+            actions: vec![WaypointAction::default()],
+            speed_keys: Vec::new(),
         }
     }
 
+    /// The segments the path is built from, starting with a [Point](PathSegment::Point)
+    pub fn segments(&self) -> &[PathSegment] {
+        &self.segments
+    }
+
+    /// Add a segment without any waypoint actions
+    fn push(&mut self, segment: PathSegment) {
+        self.segments.push(segment);
+        self.actions.push(WaypointAction::default());
+    }
+
     /// Add a line from the previous point to the passed [point](bevy::math::f32::Vec2)
     pub fn add_line_to(&mut self, point: Vec2) {
-        self.segments.push(Point(point));
+        self.push(Point(point));
+    }
+
+    /// Add a line from the previous point to the passed [point](bevy::math::f32::Vec2),
+    /// carrying out *action* when a follower reaches it
+    pub fn add_line_to_with_action(&mut self, point: Vec2, action: WaypointAction) {
+        self.add_line_to(point);
+        self.set_action(action);
+    }
+
+    /// Set the [actions](WaypointAction) for the end point of the last added segment
+    pub fn set_action(&mut self, action: WaypointAction) {
+        if let Some(last) = self.actions.last_mut() {
+            *last = action;
+        }
+    }
+
+    /// Set the speed followers move at when passing the end point of the last added segment
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed_keys.push(SpeedKey {
+            at: KeyPosition::Waypoint(self.segments.len() - 1),
+            speed,
+        });
+    }
+
+    /// Add a [key](SpeedKey) for the speed followers move at along the path
+    pub fn add_speed_key(&mut self, key: SpeedKey) {
+        self.speed_keys.push(key);
     }
 
     /// Add a bezier curve from the previous to the specified end [point](bevy::math::f32::Vec2)
@@ -105,7 +575,7 @@ impl PathBuilder {
         ctrl2: Vec2,
         straightness: f32,
     ) {
-        self.segments.push(CubicBezierCurve {
+        self.push(CubicBezierCurve {
             to,
             ctrl1,
             ctrl2,
@@ -116,7 +586,7 @@ impl PathBuilder {
     /// Add a bezier curve from the previous to the specified end [point](bevy::math::f32::Vec2)
     /// using a single control point and the given straightness
     pub fn add_quadratic_bezier_curve(&mut self, to: Vec2, ctrl: Vec2, straightness: f32) {
-        self.segments.push(QuadraticBezierCurve {
+        self.push(QuadraticBezierCurve {
             to,
             ctrl,
             straightness,
@@ -125,7 +595,13 @@ impl PathBuilder {
 
     /// Build a list of [points](bevy::math::f32::Vec2) from the current segments
     pub fn build_points(&self) -> Vec<Vec2> {
+        self.build_points_and_waypoints().0
+    }
+
+    /// Build the [points](bevy::math::f32::Vec2) and the indices of the ends of the segments
+    fn build_points_and_waypoints(&self) -> (Vec<Vec2>, Vec<usize>) {
         let mut path_points = Vec::new();
+        let mut waypoints = Vec::new();
 
         if let Some(segment) = self.segments.first() {
             let mut last_pos = match segment {
@@ -134,6 +610,8 @@ impl PathBuilder {
                     panic!("Path has to start with a Point")
                 }
             };
+            path_points.push(*last_pos);
+            waypoints.push(0);
 
             for segment in self.segments.iter().skip(1) {
                 match segment {
@@ -153,8 +631,11 @@ impl PathBuilder {
                             &Vec2Geo(*ctrl2),
                             &Vec2Geo(*to),
                         );
-                        let points: &mut Vec<Vec2> =
-                            &mut curve.as_points(*straightness).map(|p| p.0).collect();
+                        let points: &mut Vec<Vec2> = &mut curve
+                            .as_points(*straightness)
+                            .skip(1)
+                            .map(|p| p.0)
+                            .collect();
                         path_points.append(points);
                         last_pos = to;
                     }
@@ -165,32 +646,175 @@ impl PathBuilder {
                     } => {
                         let curve =
                             Bezier2::quadratic(&Vec2Geo(*last_pos), &Vec2Geo(*ctrl), &Vec2Geo(*to));
-                        let points: &mut Vec<Vec2> =
-                            &mut curve.as_points(*straightness).map(|p| p.0).collect();
+                        let points: &mut Vec<Vec2> = &mut curve
+                            .as_points(*straightness)
+                            .skip(1)
+                            .map(|p| p.0)
+                            .collect();
                         path_points.append(points);
                         last_pos = to;
                     }
                 }
+                waypoints.push(path_points.len() - 1);
             }
         }
-        path_points
+        (path_points, waypoints)
     }
 
     /// Build a non looping [Path](Path2) from the current segments
     pub fn build_path(&self) -> Path2 {
-        let points = self.build_points();
-        Path2 {
-            points,
-            is_loop: false,
-        }
+        self.build_path_with_mode(PathMode::Once)
     }
 
     /// Build a looping [Path](Path2) from the current segments
     pub fn build_looping_path(&self) -> Path2 {
-        let points = self.build_points();
-        Path2 {
-            points,
-            is_loop: true,
+        self.build_path_with_mode(PathMode::Loop)
+    }
+
+    /// Build a [Path](Path2) from the current segments that is traversed following *mode*
+    ///
+    /// [Closed](PathMode::is_closed) paths connect back to their first point by themselves, so if
+    /// the last segment ends on the first point, that end point and its waypoint are left out. The
+    /// first waypoint stands in for it: its action fills in whatever the first waypoint's action
+    /// leaves unset, and speed keys on it move to the first waypoint.
+    pub fn build_path_with_mode(&self, mode: PathMode) -> Path2 {
+        let (mut points, mut waypoints) = self.build_points_and_waypoints();
+        let mut actions = self.actions.clone();
+        let mut speed_keys = self.speed_keys.clone();
+        if mode.is_closed() && points.len() > 1 && points.first() == points.last() {
+            points.pop();
+            waypoints.pop();
+            let end = waypoints.len();
+            if let Some(action) = actions.pop() {
+                let first = &mut actions[0];
+                if first.wait <= 0. {
+                    first.wait = action.wait;
+                }
+                first.facing = first.facing.or(action.facing);
+                first.tag = first.tag.take().or(action.tag);
+            }
+            for key in &mut speed_keys {
+                if key.at == KeyPosition::Waypoint(end) {
+                    key.at = KeyPosition::Waypoint(0);
+                }
+            }
+        }
+        let mut path = Path2 {
+            waypoints,
+            waiting: (0..actions.len())
+                .filter(|waypoint| actions[*waypoint].wait > 0.)
+                .collect(),
+            actions,
+            ..Path2::new(points, mode)
+        };
+        path.set_speed_keys(speed_keys);
+        path
+    }
+}
+
+/// Serialized form of a [PathBuilder], where actions and speed keys can be left out
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct PathBuilderData {
+    segments: Vec<PathSegment>,
+    #[serde(default)]
+    actions: Vec<WaypointAction>,
+    #[serde(default)]
+    speed_keys: Vec<SpeedKey>,
+}
+
+#[cfg(feature = "serde")]
+impl From<PathBuilder> for PathBuilderData {
+    fn from(builder: PathBuilder) -> Self {
+        Self {
+            segments: builder.segments,
+            actions: builder.actions,
+            speed_keys: builder.speed_keys,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<PathBuilderData> for PathBuilder {
+    type Error = String;
+
+    fn try_from(data: PathBuilderData) -> Result<Self, Self::Error> {
+        if !matches!(data.segments.first(), Some(Point(_))) {
+            return Err("Path has to start with a Point".to_string());
+        }
+        let count = data.segments.len();
+        let mut actions = data.actions;
+        if actions.len() > count {
+            return Err("More actions than segments".to_string());
         }
+        actions.resize(count, WaypointAction::default());
+        Ok(Self {
+            segments: data.segments,
+            actions,
+            speed_keys: data.speed_keys,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positive_offsets_are_on_the_right() {
+        let path = Path2::new(vec![Vec2::ZERO, Vec2::new(10., 0.)], PathMode::Once);
+        assert_eq!(path.offset_at(5., 1.), Some(Vec2::new(5., -1.)));
+        assert_eq!(path.offset_at(5., -1.), Some(Vec2::new(5., 1.)));
+    }
+
+    #[test]
+    fn offsets_are_mitred_at_corners() {
+        let square = vec![
+            Vec2::ZERO,
+            Vec2::new(10., 0.),
+            Vec2::new(10., 10.),
+            Vec2::new(0., 10.),
+        ];
+        let path = Path2::new(square, PathMode::Loop);
+        let corner = path.offset_at(0., -1.).unwrap();
+        assert!(corner.distance(Vec2::ONE) < 1e-5, "{:?}", corner);
+        let corner = path.offset_at(10., 1.).unwrap();
+        assert!(corner.distance(Vec2::new(11., -1.)) < 1e-5, "{:?}", corner);
+    }
+
+    #[test]
+    fn closed_builders_move_their_end_to_the_first_waypoint() {
+        let mut builder = PathBuilder::new(Vec2::ZERO);
+        builder.set_action(WaypointAction {
+            facing: Some(1.),
+            ..default()
+        });
+        builder.add_line_to(Vec2::new(10., 0.));
+        builder.add_line_to(Vec2::new(10., 10.));
+        let home = WaypointAction {
+            wait: 2.,
+            facing: Some(3.),
+            tag: Some("home".to_string()),
+        };
+        builder.add_line_to_with_action(Vec2::ZERO, home.clone());
+        builder.set_speed(3.);
+
+        let path = builder.build_looping_path();
+        assert_eq!(path.points().len(), 3);
+        assert_eq!(path.waypoints(), &[0, 1, 2]);
+        assert_eq!(
+            path.waypoint_action(0),
+            &WaypointAction {
+                facing: Some(1.),
+                ..home.clone()
+            }
+        );
+        assert_eq!(path.waiting, vec![0]);
+        assert_eq!(path.speed_at(0.), Some(3.));
+
+        let path = builder.build_path();
+        assert_eq!(path.points().len(), 4);
+        assert_eq!(path.waypoint_action(3), &home);
+        assert_eq!(path.speed_at(0.), Some(3.));
     }
 }
@@ -1,11 +1,46 @@
+use crate::follow_path2::events::{PathFinished, WaypointReached};
+use crate::follow_path2::path::Path2;
 use crate::follow_path2::systems::advance_paths;
+use crate::timestep::{PathTimeScale, PathTimestep};
 use bevy::prelude::*;
+use bevy::time::FixedTimestep;
+
+/// Label of the [FixedTimestep] used when running FollowPath2 at a fixed rate
+pub const FOLLOW_PATH2_TIMESTEP: &str = "follow_path2_timestep";
 
 /// Plugin for FollowPath2
-pub struct FollowPath2Plugin;
+#[derive(Default)]
+pub struct FollowPath2Plugin {
+    /// Clock used to advance the paths
+    pub timestep: PathTimestep,
+}
+
+impl FollowPath2Plugin {
+    /// Create the plugin, advancing paths at a fixed rate with the given step in seconds
+    pub fn with_fixed_timestep(step: f64) -> Self {
+        Self {
+            timestep: PathTimestep::Fixed(step),
+        }
+    }
+}
 
 impl Plugin for FollowPath2Plugin {
     fn build(&self, app: &mut App) {
-        app.add_system(advance_paths);
+        app.add_event::<PathFinished>()
+            .add_event::<WaypointReached>()
+            .init_resource::<PathTimeScale>()
+            .add_asset::<Path2>();
+        #[cfg(feature = "serde")]
+        app.init_asset_loader::<crate::path_file::Path2FileLoader>();
+        match self.timestep {
+            PathTimestep::Frame => {
+                app.add_system(advance_paths);
+            }
+            PathTimestep::Fixed(step) => {
+                app.add_system(advance_paths.with_run_criteria(
+                    FixedTimestep::step(step).with_label(FOLLOW_PATH2_TIMESTEP),
+                ));
+            }
+        }
     }
 }
@@ -0,0 +1,1019 @@
+use crate::follow_path2::path::{Path2, PathBuilder, PathSegment};
+use crate::traversal::PathMode;
+use bevy::math::{Affine2, Mat2, Vec2};
+use std::f32::consts::{FRAC_PI_2, TAU};
+use std::fmt;
+
+/// How coordinates read from SVG data are mapped to world units
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SvgOptions {
+    /// Factor to multiply all coordinates by
+    pub scale: f32,
+    /// Whether to negate y coordinates, as the y axis of SVG points down
+    pub flip_y: bool,
+    /// Straightness of the curves added to the [PathBuilder], in world units
+    pub straightness: f32,
+}
+
+impl Default for SvgOptions {
+    fn default() -> Self {
+        Self {
+            scale: 1.,
+            flip_y: true,
+            straightness: 0.1,
+        }
+    }
+}
+
+impl SvgOptions {
+    /// Map a point from SVG coordinates to world units
+    fn apply(&self, point: Vec2) -> Vec2 {
+        let point = if self.flip_y {
+            Vec2::new(point.x, -point.y)
+        } else {
+            point
+        };
+        point * self.scale
+    }
+
+    /// Map a point from world units back to SVG coordinates
+    fn unapply(&self, point: Vec2) -> Vec2 {
+        let point = point / self.scale;
+        // Adding zero turns negative zeros into positive ones, so they are written as 0
+        if self.flip_y {
+            Vec2::new(point.x, -point.y) + Vec2::ZERO
+        } else {
+            point + Vec2::ZERO
+        }
+    }
+}
+
+/// How paths are written as SVG documents
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SvgExportOptions {
+    /// Mapping from SVG coordinates to world units, undone when writing
+    ///
+    /// Using the same options to read the document back gives the original coordinates.
+    pub coordinates: SvgOptions,
+    /// Radius of the circles marking waypoints, or [None] to leave them out
+    pub waypoint_markers: Option<f32>,
+    /// Width of the line drawn along the path
+    pub stroke_width: f32,
+}
+
+impl Default for SvgExportOptions {
+    fn default() -> Self {
+        Self {
+            coordinates: SvgOptions::default(),
+            waypoint_markers: None,
+            stroke_width: 1.,
+        }
+    }
+}
+
+/// A path read from SVG data
+#[derive(Clone)]
+pub struct SvgPath {
+    /// Builder holding a segment for every command of the path data
+    pub builder: PathBuilder,
+    /// Whether the path data ended by closing the path
+    ///
+    /// The line back to the start of a closed path is left to [looping](PathMode::Loop).
+    pub closed: bool,
+}
+
+impl SvgPath {
+    /// Build a [Path](Path2) that loops if the path data was closed
+    pub fn build_path(&self) -> Path2 {
+        if self.closed {
+            self.builder.build_path_with_mode(PathMode::Loop)
+        } else {
+            self.builder.build_path_with_mode(PathMode::Once)
+        }
+    }
+}
+
+/// Errors from reading SVG data
+#[derive(Debug)]
+pub enum SvgError {
+    /// The SVG file could not be read
+    Io(std::io::Error),
+    /// The path data does not start with a move command
+    MissingMoveTo,
+    /// An unknown command was found at the byte offset into the path data
+    UnknownCommand(char, usize),
+    /// A number was expected at the byte offset into the path data
+    InvalidNumber(usize),
+    /// A number was found at the byte offset into the path data where a command was expected
+    UnexpectedNumber(usize),
+    /// An arc flag was expected at the byte offset into the path data
+    InvalidFlag(usize),
+    /// A transform could not be read at the byte offset into the `transform` attribute
+    InvalidTransform(usize),
+    /// The attributes of an element could not be read
+    MalformedElement,
+}
+
+impl fmt::Display for SvgError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SvgError::Io(error) => write!(f, "Could not read SVG file: {}", error),
+            SvgError::MissingMoveTo => write!(f, "Path data has to start with a move command"),
+            SvgError::UnknownCommand(command, position) => {
+                write!(f, "Unknown command '{}' at {}", command, position)
+            }
+            SvgError::InvalidNumber(position) => write!(f, "Expected a number at {}", position),
+            SvgError::UnexpectedNumber(position) => {
+                write!(f, "Expected a command at {}", position)
+            }
+            SvgError::InvalidFlag(position) => write!(f, "Expected a flag at {}", position),
+            SvgError::InvalidTransform(position) => {
+                write!(f, "Invalid transform at {}", position)
+            }
+            SvgError::MalformedElement => write!(f, "Malformed element"),
+        }
+    }
+}
+
+impl std::error::Error for SvgError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SvgError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for SvgError {
+    fn from(error: std::io::Error) -> Self {
+        SvgError::Io(error)
+    }
+}
+
+/// Read the paths of all `path` elements in the SVG file at *path*
+///
+/// See [parse_svg] for what is read from the file.
+pub fn read_svg_file(
+    path: impl AsRef<std::path::Path>,
+    options: &SvgOptions,
+) -> Result<Vec<SvgPath>, SvgError> {
+    parse_svg(&std::fs::read_to_string(path)?, options)
+}
+
+/// Read the paths of all `path` elements in an SVG document, in document order
+///
+/// The `transform` attributes of the elements and the groups around them are applied to their
+/// path data, before mapping it with the *options*. Elements without path data are skipped.
+pub fn parse_svg(svg: &str, options: &SvgOptions) -> Result<Vec<SvgPath>, SvgError> {
+    let mut paths = Vec::new();
+    // Transforms of the elements that are open, each including the transforms of its ancestors
+    let mut transforms: Vec<Affine2> = Vec::new();
+    let mut rest = svg;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start..];
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment
+                .find("-->")
+                .map_or("", |end| &comment[end + "-->".len()..]);
+            continue;
+        }
+        let element = &rest[1..];
+        let end = element.find('>').ok_or(SvgError::MalformedElement)?;
+        rest = &element[end + 1..];
+        let tag = &element[..end];
+        if tag.starts_with('/') {
+            transforms.pop();
+            continue;
+        }
+        // Declarations and processing instructions
+        if tag.starts_with('!') || tag.starts_with('?') {
+            continue;
+        }
+
+        let name_end = tag
+            .find(|c: char| c.is_whitespace() || c == '/')
+            .unwrap_or(tag.len());
+        let (name, attributes) = tag.split_at(name_end);
+        let parent = transforms.last().copied().unwrap_or(Affine2::IDENTITY);
+        let transform = match attribute(attributes, "transform")? {
+            Some(list) => parent * parse_transform(list)?,
+            None => parent,
+        };
+        if name == "path" {
+            if let Some(data) = attribute(attributes, "d")? {
+                paths.push(parse_transformed_path_data(data, transform, options)?);
+            }
+        }
+        if !tag.ends_with('/') {
+            transforms.push(transform);
+        }
+    }
+    Ok(paths)
+}
+
+/// Find the value of the attribute called *name* among the *attributes* of an element
+fn attribute<'a>(attributes: &'a str, name: &str) -> Result<Option<&'a str>, SvgError> {
+    let mut rest = attributes.trim_start();
+    while !rest.is_empty() && !rest.starts_with('/') {
+        let (key, value) = rest.split_once('=').ok_or(SvgError::MalformedElement)?;
+        let value = value.trim_start();
+        let quote = value
+            .chars()
+            .next()
+            .filter(|c| *c == '"' || *c == '\'')
+            .ok_or(SvgError::MalformedElement)?;
+        let (value, after) = value[1..]
+            .split_once(quote)
+            .ok_or(SvgError::MalformedElement)?;
+        if key.trim_end() == name {
+            return Ok(Some(value));
+        }
+        rest = after.trim_start();
+    }
+    Ok(None)
+}
+
+/// Parse the list of transforms in a `transform` attribute into a single transform
+fn parse_transform(list: &str) -> Result<Affine2, SvgError> {
+    let mut parser = Parser {
+        data: list.as_bytes(),
+        position: 0,
+    };
+    let mut transform = Affine2::IDENTITY;
+    while parser.skip_separators() {
+        let start = parser.position;
+        let name_length = list[start..]
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(list.len() - start);
+        let name = &list[start..start + name_length];
+        parser.position += name_length;
+        parser.skip_separators();
+        if parser.data.get(parser.position) != Some(&b'(') {
+            return Err(SvgError::InvalidTransform(start));
+        }
+        parser.position += 1;
+        let mut values = Vec::new();
+        while parser.skip_separators() && parser.data[parser.position] != b')' {
+            values.push(parser.number()?);
+        }
+        if parser.position == parser.data.len() {
+            return Err(SvgError::InvalidTransform(start));
+        }
+        parser.position += 1;
+
+        let next = match (name, values.as_slice()) {
+            ("matrix", [a, b, c, d, e, f]) => Affine2::from_cols_array(&[*a, *b, *c, *d, *e, *f]),
+            ("translate", [x]) => Affine2::from_translation(Vec2::new(*x, 0.)),
+            ("translate", [x, y]) => Affine2::from_translation(Vec2::new(*x, *y)),
+            ("scale", [scale]) => Affine2::from_scale(Vec2::splat(*scale)),
+            ("scale", [x, y]) => Affine2::from_scale(Vec2::new(*x, *y)),
+            ("rotate", [angle]) => Affine2::from_angle(angle.to_radians()),
+            ("rotate", [angle, x, y]) => {
+                let center = Vec2::new(*x, *y);
+                Affine2::from_translation(center)
+                    * Affine2::from_angle(angle.to_radians())
+                    * Affine2::from_translation(-center)
+            }
+            ("skewX", [angle]) => Affine2::from_mat2(Mat2::from_cols(
+                Vec2::X,
+                Vec2::new(angle.to_radians().tan(), 1.),
+            )),
+            ("skewY", [angle]) => Affine2::from_mat2(Mat2::from_cols(
+                Vec2::new(1., angle.to_radians().tan()),
+                Vec2::Y,
+            )),
+            _ => return Err(SvgError::InvalidTransform(start)),
+        };
+        transform = transform * next;
+    }
+    Ok(transform)
+}
+
+/// Parse the SVG path data in *data*, the contents of the `d` attribute of a `path` element
+///
+/// Every command adds a segment ending in a waypoint, with elliptical arcs split into cubic bezier
+/// curves. As followers need a connected path, moving to the start of another subpath adds a line
+/// to it. A closed subpath gets a line back to its start if more commands follow, or if it is the
+/// last one and starts somewhere else than the path.
+pub fn parse_path_data(data: &str, options: &SvgOptions) -> Result<SvgPath, SvgError> {
+    parse_transformed_path_data(data, Affine2::IDENTITY, options)
+}
+
+/// Parse the SVG path data in *data*, applying *transform* before the *options*
+fn parse_transformed_path_data(
+    data: &str,
+    transform: Affine2,
+    options: &SvgOptions,
+) -> Result<SvgPath, SvgError> {
+    let map = |point: Vec2| options.apply(transform.transform_point2(point));
+    let mut parser = Parser {
+        data: data.as_bytes(),
+        position: 0,
+    };
+    let mut builder: Option<PathBuilder> = None;
+    let mut closed = false;
+    let mut closing: Option<Vec2> = None;
+    let mut first = Vec2::ZERO;
+    let mut current = Vec2::ZERO;
+    let mut subpath_start = Vec2::ZERO;
+    let mut previous_cubic_ctrl: Option<Vec2> = None;
+    let mut previous_quadratic_ctrl: Option<Vec2> = None;
+    let mut command: Option<u8> = None;
+
+    while parser.skip_separators() {
+        if let Some(next) = parser.command() {
+            command = Some(next);
+        } else {
+            // Coordinates without a command repeat the previous one, with moves turning into lines
+            command = match command {
+                Some(b'M') => Some(b'L'),
+                Some(b'm') => Some(b'l'),
+                Some(b'Z' | b'z') => return Err(SvgError::UnexpectedNumber(parser.position)),
+                other => other,
+            };
+        }
+        let command = command.ok_or(SvgError::MissingMoveTo)?;
+        let kind = command.to_ascii_uppercase();
+        let relative = command.is_ascii_lowercase();
+        let origin = if relative { current } else { Vec2::ZERO };
+
+        let builder = match (&mut builder, kind) {
+            (None, b'M') => {
+                current = origin + parser.point()?;
+                first = current;
+                subpath_start = current;
+                builder = Some(PathBuilder::new(map(current)));
+                continue;
+            }
+            (None, _) => return Err(SvgError::MissingMoveTo),
+            (Some(builder), _) => builder,
+        };
+
+        let mut cubic_ctrl = None;
+        let mut quadratic_ctrl = None;
+        if kind != b'Z' {
+            if let Some(start) = closing.take() {
+                builder.add_line_to(map(start));
+            }
+            closed = false;
+        }
+        match kind {
+            b'M' => {
+                current = origin + parser.point()?;
+                subpath_start = current;
+                builder.add_line_to(map(current));
+            }
+            b'L' => {
+                current = origin + parser.point()?;
+                builder.add_line_to(map(current));
+            }
+            b'H' => {
+                current.x = origin.x + parser.number()?;
+                builder.add_line_to(map(current));
+            }
+            b'V' => {
+                current.y = origin.y + parser.number()?;
+                builder.add_line_to(map(current));
+            }
+            b'C' | b'S' => {
+                let ctrl1 = if kind == b'C' {
+                    origin + parser.point()?
+                } else {
+                    previous_cubic_ctrl.map_or(current, |ctrl| 2. * current - ctrl)
+                };
+                let ctrl2 = origin + parser.point()?;
+                current = origin + parser.point()?;
+                builder.add_cubic_bezier_curve(
+                    map(current),
+                    map(ctrl1),
+                    map(ctrl2),
+                    options.straightness,
+                );
+                cubic_ctrl = Some(ctrl2);
+            }
+            b'Q' | b'T' => {
+                let ctrl = if kind == b'Q' {
+                    origin + parser.point()?
+                } else {
+                    previous_quadratic_ctrl.map_or(current, |ctrl| 2. * current - ctrl)
+                };
+                current = origin + parser.point()?;
+                builder.add_quadratic_bezier_curve(map(current), map(ctrl), options.straightness);
+                quadratic_ctrl = Some(ctrl);
+            }
+            b'A' => {
+                let radii = parser.point()?;
+                let rotation = parser.number()?.to_radians();
+                let large_arc = parser.flag()?;
+                let sweep = parser.flag()?;
+                let to = origin + parser.point()?;
+                // Arcs that end where they start are left out, as the SVG specification asks
+                if to != current {
+                    let curves = arc_to_cubics(current, to, radii, rotation, large_arc, sweep);
+                    if curves.is_empty() {
+                        builder.add_line_to(map(to));
+                    }
+                    for (ctrl1, ctrl2, end) in curves {
+                        builder.add_cubic_bezier_curve(
+                            map(end),
+                            map(ctrl1),
+                            map(ctrl2),
+                            options.straightness,
+                        );
+                    }
+                }
+                current = to;
+            }
+            b'Z' => {
+                if current != subpath_start {
+                    closing = Some(subpath_start);
+                }
+                current = subpath_start;
+                closed = true;
+            }
+            _ => {
+                return Err(SvgError::UnknownCommand(
+                    char::from(command),
+                    parser.position - 1,
+                ))
+            }
+        }
+        previous_cubic_ctrl = cubic_ctrl;
+        previous_quadratic_ctrl = quadratic_ctrl;
+    }
+
+    let mut builder = builder.ok_or(SvgError::MissingMoveTo)?;
+    // Looping only leads back to the first point, not to the start of a later subpath
+    if let Some(start) = closing.filter(|start| *start != first) {
+        builder.add_line_to(map(start));
+    }
+    Ok(SvgPath { builder, closed })
+}
+
+/// Split an elliptical arc into cubic bezier curves of at most a quarter turn each
+///
+/// Returns the control points and end point of each curve, or nothing if the arc is drawn as a
+/// line. Radii that are too small to reach *to* are scaled up, as described by the SVG
+/// specification.
+fn arc_to_cubics(
+    from: Vec2,
+    to: Vec2,
+    radii: Vec2,
+    rotation: f32,
+    large_arc: bool,
+    sweep: bool,
+) -> Vec<(Vec2, Vec2, Vec2)> {
+    let mut radii = radii.abs();
+    if from == to || radii.x == 0. || radii.y == 0. {
+        return Vec::new();
+    }
+    let rotate = Mat2::from_angle(rotation);
+    let half = rotate.transpose() * ((from - to) / 2.);
+    let scale = (half / radii).length_squared();
+    if scale > 1. {
+        radii *= scale.sqrt();
+    }
+
+    let numerator =
+        (radii.x * radii.y).powi(2) - (radii.x * half.y).powi(2) - (radii.y * half.x).powi(2);
+    let denominator = (radii.x * half.y).powi(2) + (radii.y * half.x).powi(2);
+    let sign = if large_arc == sweep { -1. } else { 1. };
+    let factor = sign * (numerator / denominator).max(0.).sqrt();
+    let center_offset = factor * Vec2::new(radii.x * half.y / radii.y, -radii.y * half.x / radii.x);
+    let center = rotate * center_offset + (from + to) / 2.;
+
+    let start = (half - center_offset) / radii;
+    let end = (-half - center_offset) / radii;
+    let start_angle = start.y.atan2(start.x);
+    let mut sweep_angle = end.y.atan2(end.x) - start_angle;
+    if sweep && sweep_angle < 0. {
+        sweep_angle += TAU;
+    } else if !sweep && sweep_angle > 0. {
+        sweep_angle -= TAU;
+    }
+
+    // Rounding errors should not split exact quarter turns in two
+    let count = (sweep_angle.abs() / FRAC_PI_2 - 1e-4).ceil().max(1.) as usize;
+    let step = sweep_angle / count as f32;
+    let handle = 4. / 3. * (step / 4.).tan();
+    let point = |angle: f32| center + rotate * (radii * Vec2::new(angle.cos(), angle.sin()));
+    let tangent = |angle: f32| rotate * (radii * Vec2::new(-angle.sin(), angle.cos()));
+    (0..count)
+        .map(|index| {
+            let from_angle = start_angle + step * index as f32;
+            let to_angle = from_angle + step;
+            let end = if index + 1 == count {
+                to
+            } else {
+                point(to_angle)
+            };
+            (
+                point(from_angle) + handle * tangent(from_angle),
+                end - handle * tangent(to_angle),
+                end,
+            )
+        })
+        .collect()
+}
+
+/// Reads commands and numbers from SVG path data
+struct Parser<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl Parser<'_> {
+    /// Skip whitespace and commas, returning whether any data is left
+    fn skip_separators(&mut self) -> bool {
+        while self
+            .data
+            .get(self.position)
+            .is_some_and(|byte| byte.is_ascii_whitespace() || *byte == b',')
+        {
+            self.position += 1;
+        }
+        self.position < self.data.len()
+    }
+
+    /// Read a command letter, if the next byte is one
+    fn command(&mut self) -> Option<u8> {
+        let byte = *self.data.get(self.position)?;
+        if byte.is_ascii_alphabetic() {
+            self.position += 1;
+            Some(byte)
+        } else {
+            None
+        }
+    }
+
+    /// Read a number, which may start with a sign and have a fraction and exponent
+    fn number(&mut self) -> Result<f32, SvgError> {
+        self.skip_separators();
+        let start = self.position;
+        let digits = |parser: &mut Self| {
+            let from = parser.position;
+            while parser
+                .data
+                .get(parser.position)
+                .is_some_and(u8::is_ascii_digit)
+            {
+                parser.position += 1;
+            }
+            parser.position > from
+        };
+        if matches!(self.data.get(self.position), Some(b'+' | b'-')) {
+            self.position += 1;
+        }
+        let mut any_digits = digits(self);
+        if self.data.get(self.position) == Some(&b'.') {
+            self.position += 1;
+            any_digits |= digits(self);
+        }
+        if !any_digits {
+            self.position = start;
+            return Err(SvgError::InvalidNumber(start));
+        }
+        if matches!(self.data.get(self.position), Some(b'e' | b'E')) {
+            let mantissa_end = self.position;
+            self.position += 1;
+            if matches!(self.data.get(self.position), Some(b'+' | b'-')) {
+                self.position += 1;
+            }
+            if !digits(self) {
+                self.position = mantissa_end;
+            }
+        }
+        std::str::from_utf8(&self.data[start..self.position])
+            .ok()
+            .and_then(|number| number.parse().ok())
+            .ok_or(SvgError::InvalidNumber(start))
+    }
+
+    /// Read an x and y coordinate pair
+    fn point(&mut self) -> Result<Vec2, SvgError> {
+        Ok(Vec2::new(self.number()?, self.number()?))
+    }
+
+    /// Read an arc flag, which is a single `0` or `1` that needs no separator after it
+    fn flag(&mut self) -> Result<bool, SvgError> {
+        self.skip_separators();
+        let flag = match self.data.get(self.position) {
+            Some(b'0') => false,
+            Some(b'1') => true,
+            _ => return Err(SvgError::InvalidFlag(self.position)),
+        };
+        self.position += 1;
+        Ok(flag)
+    }
+}
+
+/// Write *path* as an SVG document, with a line through its points
+///
+/// The path data is closed if the path [loops](PathMode::is_closed).
+pub fn path_to_svg(path: &Path2, options: &SvgExportOptions) -> String {
+    let coordinates = &options.coordinates;
+    let points: Vec<Vec2> = path
+        .points()
+        .iter()
+        .map(|point| coordinates.unapply(*point))
+        .collect();
+    let mut data = String::new();
+    for (index, point) in points.iter().enumerate() {
+        let command = if index == 0 { 'M' } else { 'L' };
+        data.push_str(&format!("{} {} {} ", command, point.x, point.y));
+    }
+    let waypoints: Vec<Vec2> = path
+        .waypoints()
+        .iter()
+        .map(|index| points[*index])
+        .collect();
+    svg_document(data, path.mode.is_closed(), &points, &waypoints, options)
+}
+
+/// Write the segments of *builder* as an SVG document, keeping bezier curves as curves
+///
+/// The path data is closed if *mode* [loops](PathMode::is_closed).
+pub fn builder_to_svg(builder: &PathBuilder, mode: PathMode, options: &SvgExportOptions) -> String {
+    let map = |point: &Vec2| options.coordinates.unapply(*point);
+    let mut data = String::new();
+    let mut bounds = Vec::new();
+    let mut waypoints = Vec::new();
+    for (index, segment) in builder.segments().iter().enumerate() {
+        let end = match segment {
+            PathSegment::Point(point) => {
+                let point = map(point);
+                let command = if index == 0 { 'M' } else { 'L' };
+                data.push_str(&format!("{} {} {} ", command, point.x, point.y));
+                point
+            }
+            PathSegment::CubicBezierCurve {
+                to, ctrl1, ctrl2, ..
+            } => {
+                let (to, ctrl1, ctrl2) = (map(to), map(ctrl1), map(ctrl2));
+                data.push_str(&format!(
+                    "C {} {} {} {} {} {} ",
+                    ctrl1.x, ctrl1.y, ctrl2.x, ctrl2.y, to.x, to.y
+                ));
+                bounds.extend([ctrl1, ctrl2]);
+                to
+            }
+            PathSegment::QuadraticBezierCurve { to, ctrl, .. } => {
+                let (to, ctrl) = (map(to), map(ctrl));
+                data.push_str(&format!("Q {} {} {} {} ", ctrl.x, ctrl.y, to.x, to.y));
+                bounds.push(ctrl);
+                to
+            }
+        };
+        bounds.push(end);
+        waypoints.push(end);
+    }
+    svg_document(data, mode.is_closed(), &bounds, &waypoints, options)
+}
+
+/// Wrap the path *data* in an SVG document whose view box fits all of *bounds*
+fn svg_document(
+    mut data: String,
+    closed: bool,
+    bounds: &[Vec2],
+    waypoints: &[Vec2],
+    options: &SvgExportOptions,
+) -> String {
+    if closed && !data.is_empty() {
+        data.push('Z');
+    }
+    let margin = options
+        .stroke_width
+        .max(options.waypoint_markers.unwrap_or(0.));
+    let (min, max) = if bounds.is_empty() {
+        (Vec2::ZERO, Vec2::ZERO)
+    } else {
+        bounds.iter().fold(
+            (Vec2::splat(f32::INFINITY), Vec2::splat(f32::NEG_INFINITY)),
+            |(min, max), point| (min.min(*point), max.max(*point)),
+        )
+    };
+    let min = min - margin;
+    let size = max + margin - min;
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"{} {} {} {}\">\n",
+        min.x, min.y, size.x, size.y
+    );
+    svg.push_str(&format!(
+        "  <path d=\"{}\" fill=\"none\" stroke=\"black\" stroke-width=\"{}\"/>\n",
+        data.trim_end(),
+        options.stroke_width
+    ));
+    if let Some(radius) = options.waypoint_markers {
+        for waypoint in waypoints {
+            svg.push_str(&format!(
+                "  <circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"red\"/>\n",
+                waypoint.x, waypoint.y, radius
+            ));
+        }
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UNCHANGED: SvgOptions = SvgOptions {
+        scale: 1.,
+        flip_y: false,
+        straightness: 0.1,
+    };
+
+    fn ends(path: &SvgPath) -> Vec<Vec2> {
+        path.builder
+            .segments()
+            .iter()
+            .map(|segment| match segment {
+                PathSegment::Point(to)
+                | PathSegment::CubicBezierCurve { to, .. }
+                | PathSegment::QuadraticBezierCurve { to, .. } => *to,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn commands_are_absolute_or_relative() {
+        let path = parse_path_data("M1 1 L3 1 l0 2 H0 h1 V5 v-1 m1 1", &UNCHANGED).unwrap();
+        assert_eq!(
+            ends(&path),
+            vec![
+                Vec2::new(1., 1.),
+                Vec2::new(3., 1.),
+                Vec2::new(3., 3.),
+                Vec2::new(0., 3.),
+                Vec2::new(1., 3.),
+                Vec2::new(1., 5.),
+                Vec2::new(1., 4.),
+                Vec2::new(2., 5.),
+            ]
+        );
+        assert!(!path.closed);
+    }
+
+    #[test]
+    fn numbers_without_a_command_repeat_the_previous_one() {
+        let path = parse_path_data("M0 0 1 0 1 1 l1 0 0 1", &UNCHANGED).unwrap();
+        assert_eq!(
+            ends(&path),
+            vec![
+                Vec2::ZERO,
+                Vec2::new(1., 0.),
+                Vec2::ONE,
+                Vec2::new(2., 1.),
+                Vec2::new(2., 2.),
+            ]
+        );
+        let path = parse_path_data("M0-1.5.5 1", &UNCHANGED).unwrap();
+        assert_eq!(ends(&path), vec![Vec2::new(0., -1.5), Vec2::new(0.5, 1.)]);
+    }
+
+    #[test]
+    fn smooth_curves_reflect_the_previous_control_point() {
+        let path = parse_path_data("M0 0 C1 1 2 1 3 0 S5 -1 6 0", &UNCHANGED).unwrap();
+        assert!(matches!(
+            path.builder.segments()[2],
+            PathSegment::CubicBezierCurve { to, ctrl1, ctrl2, .. }
+                if to == Vec2::new(6., 0.) && ctrl1 == Vec2::new(4., -1.) && ctrl2 == Vec2::new(5., -1.)
+        ));
+        let path = parse_path_data("M0 0 Q1 1 2 0 T4 0", &UNCHANGED).unwrap();
+        assert!(matches!(
+            path.builder.segments()[2],
+            PathSegment::QuadraticBezierCurve { to, ctrl, .. }
+                if to == Vec2::new(4., 0.) && ctrl == Vec2::new(3., -1.)
+        ));
+    }
+
+    #[test]
+    fn smooth_curves_after_other_commands_start_at_the_current_point() {
+        let path = parse_path_data("M0 0 L1 0 S2 1 3 0 L4 0 T5 0", &UNCHANGED).unwrap();
+        let segments = path.builder.segments();
+        assert!(matches!(
+            segments[2],
+            PathSegment::CubicBezierCurve { ctrl1, .. } if ctrl1 == Vec2::new(1., 0.)
+        ));
+        assert!(matches!(
+            segments[4],
+            PathSegment::QuadraticBezierCurve { ctrl, .. } if ctrl == Vec2::new(4., 0.)
+        ));
+    }
+
+    #[test]
+    fn arcs_end_on_their_circle() {
+        // Flags need no separators: large arc 0, sweep 1, then the end point 2,0
+        let path = parse_path_data("M0 0 A1 1 0 012 0", &UNCHANGED).unwrap();
+        let ends = ends(&path);
+        assert_eq!(ends.len(), 3, "{:?}", ends);
+        assert!(ends[1].distance(Vec2::new(1., -1.)) < 1e-5, "{:?}", ends);
+        assert!(ends[2].distance(Vec2::new(2., 0.)) < 1e-5, "{:?}", ends);
+        for segment in &path.builder.segments()[1..] {
+            if let PathSegment::CubicBezierCurve { to, .. } = segment {
+                assert!((to.distance(Vec2::new(1., 0.)) - 1.).abs() < 1e-5);
+            } else {
+                panic!("Expected a curve, got {:?}", segment);
+            }
+        }
+    }
+
+    #[test]
+    fn arcs_without_length_or_radius() {
+        let path = parse_path_data("M1 1 A1 1 0 0 1 1 1 L2 1", &UNCHANGED).unwrap();
+        assert_eq!(ends(&path), vec![Vec2::ONE, Vec2::new(2., 1.)]);
+        let path = parse_path_data("M1 1 A0 1 0 0 1 3 1", &UNCHANGED).unwrap();
+        assert_eq!(
+            path.builder.segments(),
+            &[
+                PathSegment::Point(Vec2::ONE),
+                PathSegment::Point(Vec2::new(3., 1.))
+            ]
+        );
+    }
+
+    #[test]
+    fn closed_subpaths_followed_by_more_commands_return_to_their_start() {
+        let path = parse_path_data("M0 0 h1 v1 z m5 0 h1", &UNCHANGED).unwrap();
+        assert_eq!(
+            ends(&path),
+            vec![
+                Vec2::ZERO,
+                Vec2::new(1., 0.),
+                Vec2::ONE,
+                Vec2::ZERO,
+                Vec2::new(5., 0.),
+                Vec2::new(6., 0.),
+            ]
+        );
+        assert!(!path.closed);
+
+        let path = parse_path_data("M0 0 h1 v1 z", &UNCHANGED).unwrap();
+        assert!(path.closed);
+        let path = path.build_path();
+        assert_eq!(path.mode, PathMode::Loop);
+        assert_eq!(path.points(), &[Vec2::ZERO, Vec2::new(1., 0.), Vec2::ONE]);
+    }
+
+    #[test]
+    fn closed_last_subpaths_return_to_their_own_start() {
+        let path = parse_path_data("M0 0 L10 0 L10 10 Z M20 0 L30 0 L30 10 Z", &UNCHANGED).unwrap();
+        assert!(path.closed);
+        let path = path.build_path();
+        assert_eq!(
+            path.points(),
+            &[
+                Vec2::ZERO,
+                Vec2::new(10., 0.),
+                Vec2::new(10., 10.),
+                Vec2::ZERO,
+                Vec2::new(20., 0.),
+                Vec2::new(30., 0.),
+                Vec2::new(30., 10.),
+                Vec2::new(20., 0.),
+            ]
+        );
+        assert_eq!(path.mode, PathMode::Loop);
+    }
+
+    #[test]
+    fn closed_paths_ending_on_their_start_leave_out_the_end() {
+        let open_end = parse_path_data("M0 0 h1 v1 h-1 z", &UNCHANGED).unwrap();
+        let closed_end = parse_path_data("M0 0 h1 v1 h-1 v-1 z", &UNCHANGED).unwrap();
+        assert_eq!(
+            open_end.build_path().points(),
+            closed_end.build_path().points()
+        );
+        assert_eq!(closed_end.build_path().points().len(), 4);
+    }
+
+    #[test]
+    fn options_scale_and_flip_coordinates() {
+        let options = SvgOptions {
+            scale: 2.,
+            flip_y: true,
+            straightness: 0.1,
+        };
+        let path = parse_path_data("M1 2 3 4", &options).unwrap();
+        assert_eq!(ends(&path), vec![Vec2::new(2., -4.), Vec2::new(6., -8.)]);
+    }
+
+    #[test]
+    fn invalid_path_data() {
+        let parse = |data| parse_path_data(data, &UNCHANGED).err();
+        assert!(matches!(parse(""), Some(SvgError::MissingMoveTo)));
+        assert!(matches!(parse("L1 1"), Some(SvgError::MissingMoveTo)));
+        assert!(matches!(
+            parse("M0 0 X1"),
+            Some(SvgError::UnknownCommand('X', 5))
+        ));
+        assert!(matches!(parse("M0 0 L1"), Some(SvgError::InvalidNumber(_))));
+        assert!(matches!(
+            parse("M0 0 z 1 1"),
+            Some(SvgError::UnexpectedNumber(_))
+        ));
+        assert!(matches!(
+            parse("M0 0 A1 1 0 2 0 1 1"),
+            Some(SvgError::InvalidFlag(_))
+        ));
+    }
+
+    #[test]
+    fn transforms_of_paths_and_their_groups_are_applied() {
+        let svg = r#"<?xml version="1.0"?>
+            <!-- <path d="M9 9"/> -->
+            <svg xmlns="http://www.w3.org/2000/svg">
+              <g transform="translate(10 20)">
+                <g transform="scale(2)">
+                  <path transform="rotate(90)" d="M1 0 L2 0"/>
+                </g>
+                <path d="M0 0"/>
+              </g>
+              <path transform="matrix(1 0 0 1 5 6) scale(3, 1)" d="M1 1"/>
+              <path d="M1 1"/>
+            </svg>"#;
+        let paths = parse_svg(svg, &UNCHANGED).unwrap();
+        assert_eq!(paths.len(), 4);
+        let rotated = ends(&paths[0]);
+        assert!(
+            rotated[0].distance(Vec2::new(10., 22.)) < 1e-5,
+            "{:?}",
+            rotated
+        );
+        assert!(
+            rotated[1].distance(Vec2::new(10., 24.)) < 1e-5,
+            "{:?}",
+            rotated
+        );
+        assert_eq!(ends(&paths[1]), vec![Vec2::new(10., 20.)]);
+        assert_eq!(ends(&paths[2]), vec![Vec2::new(8., 7.)]);
+        assert_eq!(ends(&paths[3]), vec![Vec2::ONE]);
+    }
+
+    #[test]
+    fn invalid_transforms() {
+        let parse = |svg| parse_svg(svg, &UNCHANGED).err();
+        assert!(matches!(
+            parse(r#"<path transform="shear(1)" d="M0 0"/>"#),
+            Some(SvgError::InvalidTransform(0))
+        ));
+        assert!(matches!(
+            parse(r#"<path transform="scale(1" d="M0 0"/>"#),
+            Some(SvgError::InvalidTransform(0))
+        ));
+        assert!(matches!(
+            parse(r#"<g transform="translate(1 2 3)"><path d="M0 0"/></g>"#),
+            Some(SvgError::InvalidTransform(0))
+        ));
+    }
+
+    #[test]
+    fn exported_builders_read_back_the_same() {
+        let mut builder = PathBuilder::new(Vec2::new(1., 2.));
+        builder.add_line_to(Vec2::new(4., 2.));
+        builder.add_cubic_bezier_curve(
+            Vec2::new(6., 0.),
+            Vec2::new(5., 3.),
+            Vec2::new(6.5, 1.),
+            0.1,
+        );
+        builder.add_quadratic_bezier_curve(Vec2::new(2., -1.5), Vec2::new(4., -3.), 0.1);
+        let options = SvgExportOptions {
+            coordinates: SvgOptions {
+                scale: 2.,
+                ..SvgOptions::default()
+            },
+            waypoint_markers: Some(0.5),
+            ..SvgExportOptions::default()
+        };
+
+        for mode in [PathMode::Once, PathMode::Loop] {
+            let svg = builder_to_svg(&builder, mode, &options);
+            let paths = parse_svg(&svg, &options.coordinates).unwrap();
+            assert_eq!(paths.len(), 1, "{}", svg);
+            assert_eq!(paths[0].builder.segments(), builder.segments(), "{}", svg);
+            assert_eq!(paths[0].closed, mode.is_closed(), "{}", svg);
+        }
+    }
+
+    #[test]
+    fn exported_paths_read_back_the_same() {
+        let points = vec![
+            Vec2::ZERO,
+            Vec2::new(10., 0.),
+            Vec2::new(10., -7.5),
+            Vec2::new(-2.25, 3.),
+        ];
+        let options = SvgExportOptions::default();
+        for mode in [PathMode::Once, PathMode::Loop] {
+            let path = Path2::new(points.clone(), mode);
+            let svg = path_to_svg(&path, &options);
+            let read = parse_svg(&svg, &options.coordinates).unwrap()[0].build_path();
+            assert_eq!(read.points(), path.points(), "{}", svg);
+            assert_eq!(read.mode, mode, "{}", svg);
+        }
+    }
+}
@@ -1,41 +1,517 @@
-use crate::follow_path2::components::FollowPath2;
-use bevy::math::Vec3Swizzles;
+use crate::anchor::{path_space, Hierarchy};
+use crate::follow_path2::components::{FollowPath2, RotationMode};
+use crate::follow_path2::events::{PathFinished, WaypointReached};
+use crate::follow_path2::path::Path2;
+use crate::follow_path2::plugin::FOLLOW_PATH2_TIMESTEP;
+use crate::speed::{braking_speed, ramp};
+use crate::timestep::{delta_seconds, PathTimeScale};
+use crate::traversal::{Advance, OnFinish};
 use bevy::prelude::*;
-use euclid::approxeq::ApproxEq;
+use bevy::time::FixedTimesteps;
+use bevy::utils::HashMap;
+
+/// Query for the entities following paths, with their parents
+type Followers<'w, 's, 'a> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'a mut Transform,
+        &'a mut FollowPath2,
+        Option<&'a Parent>,
+    ),
+>;
 
 /// Moves entities along their set path
+///
+/// Systems that move [anchors](FollowPath2::anchor) should run before this one, so the entities
+/// following them are carried along in the same frame.
+#[allow(clippy::too_many_arguments)]
 pub fn advance_paths(
-    mut query: Query<(Entity, &mut Transform, &mut FollowPath2)>,
+    mut queries: ParamSet<(Followers, Hierarchy)>,
+    mut spaces: Local<HashMap<Entity, Mat4>>,
+    paths: Res<Assets<Path2>>,
     mut commands: Commands,
+    mut finished_events: EventWriter<PathFinished>,
+    mut waypoint_events: EventWriter<WaypointReached>,
+    time: Res<Time>,
+    fixed_timesteps: Res<FixedTimesteps>,
+    time_scale: Res<PathTimeScale>,
 ) {
-    for (entity, mut transform, mut follow_path) in query.iter_mut() {
-        let (distance, cur_target) = {
-            let cur_target = follow_path.path.points[follow_path.cur_target];
-            let distance = cur_target.distance(transform.translation.xy());
-            if distance.approx_eq_eps(&0., &follow_path.epsilon) {
-                let cur_target = follow_path.cur_target + 1;
-                if cur_target < follow_path.path.points.len() {
-                    follow_path.cur_target = cur_target;
-                } else if follow_path.path.is_loop {
-                    follow_path.cur_target = 0;
-                } else {
+    let delta = delta_seconds(&time, &fixed_timesteps, FOLLOW_PATH2_TIMESTEP) * time_scale.0;
+    let anchored: Vec<_> = queries
+        .p0()
+        .iter()
+        .filter_map(|(entity, _, follow_path, parent)| {
+            Some((
+                entity,
+                follow_path.anchor?,
+                parent.map(|parent| parent.get()),
+            ))
+        })
+        .collect();
+    spaces.clear();
+    for (entity, anchor, parent) in anchored {
+        if let Some(space) = path_space(anchor, parent, &queries.p1()) {
+            spaces.insert(entity, space);
+        }
+    }
+
+    for (entity, mut transform, mut follow_path, _) in queries.p0().iter_mut() {
+        let space = spaces.get(&entity).copied();
+        // Paused entities are still carried along by their anchor
+        if follow_path.paused && space.is_none() {
+            continue;
+        }
+        let follow_path = &mut *follow_path;
+        let path = match paths.get(&follow_path.path) {
+            Some(path) => path,
+            // The path has not been loaded yet
+            None => continue,
+        };
+        // Keep the progress along paths that changed, like when their file is reloaded
+        let length = path.length();
+        if let Some(previous) = follow_path.path_length.filter(|previous| *previous > 0.) {
+            follow_path.distance *= length / previous;
+        }
+        follow_path.path_length = Some(length);
+        // Stop waiting at waypoints that are gone
+        let waypoints = path.waypoints().len();
+        if follow_path
+            .dwell
+            .is_some_and(|dwell| dwell.waypoint >= waypoints)
+        {
+            follow_path.dwell = None;
+        }
+        let elapsed = if follow_path.paused {
+            0.
+        } else {
+            delta * follow_path.time_scale
+        };
+        let mut delta = elapsed;
+        if let Some(dwell) = &mut follow_path.dwell {
+            dwell.remaining -= delta;
+            if dwell.remaining > 0. {
+                delta = 0.;
+            } else {
+                // Time left over after waiting is spent moving on
+                delta = -dwell.remaining;
+                follow_path.dwell = None;
+            }
+        }
+
+        if delta > 0. {
+            let stop = path.distance_to_stop(
+                follow_path.distance,
+                follow_path.direction,
+                follow_path.cycles_completed,
+            );
+            let speed = path
+                .speed_at(follow_path.distance)
+                .unwrap_or(follow_path.speed);
+            let deceleration = if follow_path.max_deceleration.is_finite() {
+                follow_path.max_deceleration
+            } else {
+                follow_path.max_lateral_acceleration
+            };
+            let cornering = path.cornering_speed(
+                follow_path.distance,
+                follow_path.direction,
+                follow_path.max_lateral_acceleration,
+                deceleration,
+                speed,
+            );
+            let target = speed
+                .min(braking_speed(follow_path.max_deceleration, stop, delta))
+                .min(cornering);
+            follow_path.current_speed = ramp(
+                follow_path.current_speed,
+                target,
+                follow_path.max_acceleration,
+                follow_path.max_deceleration,
+                delta,
+            );
+        }
+
+        // Paused entities neither move nor finish
+        let advance = if follow_path.paused {
+            Advance::default()
+        } else {
+            path.advance(
+                &mut follow_path.distance,
+                follow_path.current_speed * delta,
+                follow_path.direction,
+                &mut follow_path.cycles_completed,
+            )
+        };
+        if advance.turned {
+            follow_path.reverse();
+            follow_path.current_speed = 0.;
+        }
+        if advance.dwell.is_some() {
+            follow_path.dwell = advance.dwell;
+            follow_path.current_speed = 0.;
+        }
+
+        for reached in advance.reached {
+            waypoint_events.send(WaypointReached {
+                entity,
+                waypoint: reached.waypoint,
+                point: reached.point,
+                completed_loop: reached.completed_loop,
+                tag: path.waypoint_action(reached.waypoint).tag.clone(),
+            });
+        }
+
+        if advance.finished {
+            finished_events.send(PathFinished {
+                entity,
+                path: follow_path.path.clone(),
+            });
+            match follow_path.on_finish {
+                OnFinish::Remove => {
                     commands.entity(entity).remove::<FollowPath2>();
-                    return;
                 }
-                let cur_target = follow_path.path.points[follow_path.cur_target];
-                (cur_target.distance(transform.translation.xy()), cur_target)
-            } else {
-                (distance, cur_target)
+                OnFinish::Idle => {}
+                OnFinish::Despawn => {
+                    commands.entity(entity).despawn_recursive();
+                }
+                OnFinish::Reverse => {
+                    follow_path.reverse();
+                    follow_path.current_speed = 0.;
+                    follow_path.cycles_completed = 0;
+                }
+            }
+        }
+
+        // The entity's transform within the space of its path
+        let mut path_transform = match space {
+            Some(space) => Transform::from_matrix(space.inverse() * transform.compute_matrix()),
+            None => *transform,
+        };
+        let distance = follow_path.distance;
+        follow_path.shift(elapsed);
+        if let Some(position) = path.offset_at(distance, follow_path.current_offset) {
+            path_transform.translation.x = position.x;
+            path_transform.translation.y = position.y;
+        }
+
+        let facing = follow_path
+            .dwell
+            .and_then(|dwell| path.waypoint_action(dwell.waypoint).facing);
+        let forward = |angle| angle - follow_path.forward_angle;
+        let heading = match follow_path.rotation_mode {
+            RotationMode::None => None,
+            _ if facing.is_some() => facing.map(forward),
+            RotationMode::FaceDirection => {
+                travel_direction(path, follow_path, distance).map(forward)
+            }
+            RotationMode::LookAhead(ahead) => {
+                look_ahead_direction(path, follow_path, distance, ahead).map(forward)
             }
+            RotationMode::Fixed(angle) => Some(angle),
         };
-        let direction = (cur_target - transform.translation.xy()).normalize();
+        if let Some(angle) = heading {
+            let (current, _, _) = path_transform.rotation.to_euler(EulerRot::ZYX);
+            let angle = follow_path.turn(current, angle, elapsed);
+
+            path_transform.rotation = Quat::from_rotation_z(angle);
+        }
+
+        if let Some(space) = space {
+            path_transform = Transform::from_matrix(space * path_transform.compute_matrix());
+        }
+        transform.translation = path_transform.translation;
+        transform.rotation = path_transform.rotation;
+    }
+}
+
+/// Angle of the direction the entity travels in at *distance*
+fn travel_direction(path: &Path2, follow_path: &FollowPath2, distance: f32) -> Option<f32> {
+    let direction = path.direction_at(distance)? * follow_path.direction.sign();
+    Some(direction.y.atan2(direction.x))
+}
 
-        let angle = direction.y.atan2(direction.x);
+/// Angle towards the point *ahead* units further along the path than *distance*
+///
+/// Falls back to the [travel direction](travel_direction) where there is nothing ahead, like at the end of the path.
+fn look_ahead_direction(
+    path: &Path2,
+    follow_path: &FollowPath2,
+    distance: f32,
+    ahead: f32,
+) -> Option<f32> {
+    let target = path.sample_at_distance(distance + ahead * follow_path.direction.sign())?;
+    match (target - path.sample_at_distance(distance)?).try_normalize() {
+        Some(direction) => Some(direction.y.atan2(direction.x)),
+        None => travel_direction(path, follow_path, distance),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::follow_path2::path::PathBuilder;
+    use crate::follow_path2::plugin::FollowPath2Plugin;
+    use crate::traversal::{PathDirection, PathMode};
+    use std::time::{Duration, Instant};
+
+    /// An app that moves followers along their paths, with its time starting at the returned instant
+    fn app() -> (App, Instant) {
+        let mut app = App::new();
+        app.add_plugin(bevy::core::CorePlugin)
+            .add_plugin(bevy::asset::AssetPlugin)
+            .init_resource::<FixedTimesteps>()
+            .add_plugin(FollowPath2Plugin::default());
+        let start = Instant::now();
+        let mut time = Time::default();
+        time.update_with_instant(start);
+        app.insert_resource(time);
+        (app, start)
+    }
+
+    /// Run a frame that took *seconds*
+    fn step(app: &mut App, now: &mut Instant, seconds: f32) {
+        *now += Duration::from_secs_f32(seconds);
+        app.world.resource_mut::<Time>().update_with_instant(*now);
+        app.update();
+    }
+
+    #[test]
+    fn reverse_on_finish_walks_back_and_forth() {
+        let (mut app, mut now) = app();
+        let mut builder = PathBuilder::new(Vec2::ZERO);
+        builder.add_line_to(Vec2::new(10., 0.));
+        let path = app
+            .world
+            .resource_mut::<Assets<Path2>>()
+            .add(builder.build_path_with_mode(PathMode::Once));
+        let mut follow_path = FollowPath2::new(path, 5.);
+        follow_path.on_finish = OnFinish::Reverse;
+        let entity = app
+            .world
+            .spawn()
+            .insert(Transform::default())
+            .insert(follow_path)
+            .id();
+
+        let mut frames = Vec::new();
+        for _ in 0..4 {
+            step(&mut app, &mut now, 1.);
+            let follow_path = app.world.get::<FollowPath2>(entity).unwrap();
+            let state = (
+                follow_path.distance,
+                follow_path.direction,
+                follow_path.cycles_completed,
+            );
+            let reached: Vec<_> = app
+                .world
+                .resource_mut::<Events<WaypointReached>>()
+                .drain()
+                .map(|reached| reached.waypoint)
+                .collect();
+            let finished = app
+                .world
+                .resource_mut::<Events<PathFinished>>()
+                .drain()
+                .count();
+            frames.push((state, reached, finished));
+        }
+        assert_eq!(
+            frames,
+            [
+                ((5., PathDirection::Forward, 0), vec![], 0),
+                ((10., PathDirection::Backward, 0), vec![1], 1),
+                ((5., PathDirection::Backward, 0), vec![], 0),
+                ((0., PathDirection::Forward, 0), vec![0], 1),
+            ]
+        );
+        let translation = app.world.get::<Transform>(entity).unwrap().translation;
+        assert_eq!(translation, Vec3::ZERO);
+    }
+
+    #[test]
+    fn shortened_paths_keep_the_progress_of_followers() {
+        let (mut app, mut now) = app();
+        let path = app.world.resource_mut::<Assets<Path2>>().add(Path2::new(
+            vec![Vec2::ZERO, Vec2::new(100., 0.)],
+            PathMode::Once,
+        ));
+        let entity = app
+            .world
+            .spawn()
+            .insert(Transform::default())
+            .insert(FollowPath2::new(path.clone(), 60.))
+            .id();
+        step(&mut app, &mut now, 1.);
+        assert_eq!(app.world.get::<FollowPath2>(entity).unwrap().distance, 60.);
+
+        app.world
+            .resource_mut::<Assets<Path2>>()
+            .get_mut(&path)
+            .unwrap()
+            .set_points(vec![Vec2::ZERO, Vec2::new(50., 0.)]);
+        app.world.get_mut::<FollowPath2>(entity).unwrap().speed = 0.;
+        step(&mut app, &mut now, 1.);
+        let follow_path = app.world.get::<FollowPath2>(entity).unwrap();
+        assert_eq!(follow_path.distance, 30.);
+        assert_eq!(app.world.resource::<Events<PathFinished>>().len(), 0);
+        let translation = app.world.get::<Transform>(entity).unwrap().translation;
+        assert!(translation.distance(Vec3::new(30., 0., 0.)) < 1e-4);
+    }
+
+    #[test]
+    fn braking_stops_on_the_last_point() {
+        let (mut app, mut now) = app();
+        let path = app.world.resource_mut::<Assets<Path2>>().add(Path2::new(
+            vec![Vec2::ZERO, Vec2::new(10., 0.), Vec2::new(10., 10.)],
+            PathMode::Once,
+        ));
+        let mut follow_path = FollowPath2::new(path, 6.);
+        follow_path.max_acceleration = 4.;
+        follow_path.max_deceleration = 4.;
+        follow_path.on_finish = OnFinish::Idle;
+        let entity = app
+            .world
+            .spawn()
+            .insert(Transform::default())
+            .insert(follow_path)
+            .id();
+
+        let mut speeds = vec![0.];
+        while app.world.resource::<Events<PathFinished>>().is_empty() {
+            assert!(speeds.len() < 100, "{:?}", speeds);
+            step(&mut app, &mut now, 0.1);
+            speeds.push(app.world.get::<FollowPath2>(entity).unwrap().current_speed);
+        }
+        let translation = app.world.get::<Transform>(entity).unwrap().translation;
+        assert_eq!(translation, Vec3::new(10., 10., 0.));
+        // The speed changes by no more than the limits allow each step, and the follower arrives
+        // slowly enough to stop within one more step
+        for pair in speeds.windows(2) {
+            assert!((pair[1] - pair[0]).abs() <= 0.4 + 1e-5, "{:?}", speeds);
+        }
+        assert!(speeds.contains(&6.), "{:?}", speeds);
+        assert!(*speeds.last().unwrap() <= 0.4, "{:?}", speeds);
+    }
+
+    #[test]
+    fn speed_changes_ramp_to_the_new_speed() {
+        let (mut app, mut now) = app();
+        let path = app.world.resource_mut::<Assets<Path2>>().add(Path2::new(
+            vec![Vec2::ZERO, Vec2::new(1000., 0.)],
+            PathMode::Once,
+        ));
+        let mut follow_path = FollowPath2::new(path, 6.);
+        follow_path.current_speed = 6.;
+        follow_path.max_acceleration = 2.;
+        follow_path.max_deceleration = 4.;
+        let entity = app
+            .world
+            .spawn()
+            .insert(Transform::default())
+            .insert(follow_path)
+            .id();
+
+        let speed = |app: &mut App, now: &mut Instant| {
+            step(app, now, 0.5);
+            app.world.get::<FollowPath2>(entity).unwrap().current_speed
+        };
+        app.world.get_mut::<FollowPath2>(entity).unwrap().speed = 1.;
+        assert_eq!(speed(&mut app, &mut now), 4.);
+        assert_eq!(speed(&mut app, &mut now), 2.);
+        assert_eq!(speed(&mut app, &mut now), 1.);
+        app.world.get_mut::<FollowPath2>(entity).unwrap().speed = 3.;
+        assert_eq!(speed(&mut app, &mut now), 2.);
+        assert_eq!(speed(&mut app, &mut now), 3.);
+        assert_eq!(speed(&mut app, &mut now), 3.);
+    }
+
+    #[test]
+    fn followers_slow_down_for_sharp_bends() {
+        let (mut app, mut now) = app();
+        let mut builder = PathBuilder::new(Vec2::ZERO);
+        builder.add_line_to(Vec2::new(50., 0.));
+        builder.add_quadratic_bezier_curve(Vec2::new(55., 5.), Vec2::new(55., 0.), 0.01);
+        builder.add_line_to(Vec2::new(55., 60.));
+        let path = builder.build_path();
+        let handle = app.world.resource_mut::<Assets<Path2>>().add(path.clone());
+        let mut follow_path = FollowPath2::new(handle, 20.);
+        follow_path.max_lateral_acceleration = 10.;
+        let entity = app
+            .world
+            .spawn()
+            .insert(Transform::default())
+            .insert(follow_path)
+            .id();
+
+        let mut speeds = Vec::new();
+        while let Some(follow_path) = app.world.get::<FollowPath2>(entity) {
+            assert!(speeds.len() < 200);
+            speeds.push((follow_path.distance, follow_path.current_speed));
+            step(&mut app, &mut now, 0.05);
+        }
+        // Without a deceleration limit, the entity brakes for the bend at about its lateral
+        // acceleration
+        for pair in speeds.windows(2) {
+            assert!(pair[0].1 - pair[1].1 <= 0.55, "{:?}", speeds);
+        }
+        // Speeds are picked once a step, so the bend may be tighter by the room to brake in a step
+        for (distance, speed) in &speeds {
+            let curvature = path.curvature_at(*distance);
+            let lateral = speed * speed * curvature;
+            let room = 2. * 10. * speed * 0.05 * curvature;
+            assert!(lateral <= 10. + room, "{} at {}", lateral, distance);
+        }
+        let slowest = speeds[1..]
+            .iter()
+            .map(|(_, speed)| *speed)
+            .fold(20., f32::min);
+        assert!(slowest > 5. && slowest < 7., "{}", slowest);
+        assert!(speeds[10..20].iter().all(|(_, speed)| *speed == 20.));
+        assert_eq!(speeds.last().unwrap().1, 20.);
+    }
+
+    /// Marks the entity moved by [move_anchor]
+    #[derive(Component)]
+    struct Platform;
+
+    fn move_anchor(mut platforms: Query<&mut Transform, With<Platform>>) {
+        for mut transform in platforms.iter_mut() {
+            transform.translation.x += 100.;
+            transform.rotate_z(std::f32::consts::FRAC_PI_2);
+        }
+    }
 
-        transform.rotation = Quat::from_rotation_z(angle);
+    #[test]
+    fn anchors_carry_followers_in_the_same_frame() {
+        let (mut app, mut now) = app();
+        app.add_system(move_anchor.before(advance_paths));
+        let path = app.world.resource_mut::<Assets<Path2>>().add(Path2::new(
+            vec![Vec2::new(1., 0.), Vec2::new(11., 0.)],
+            PathMode::Once,
+        ));
+        let anchor = app
+            .world
+            .spawn()
+            .insert(Transform::default())
+            .insert(GlobalTransform::default())
+            .insert(Platform)
+            .id();
+        let mut follow_path = FollowPath2::new(path, 0.);
+        follow_path.anchor = Some(anchor);
+        let entity = app
+            .world
+            .spawn()
+            .insert(Transform::default())
+            .insert(follow_path)
+            .id();
 
-        let speed = follow_path.speed.min(distance);
-        transform.translation.x += direction.x * speed;
-        transform.translation.y += direction.y * speed;
+        step(&mut app, &mut now, 1.);
+        let transform = app.world.get::<Transform>(entity).unwrap();
+        assert!(transform.translation.distance(Vec3::new(100., 1., 0.)) < 1e-4);
+        step(&mut app, &mut now, 1.);
+        let transform = app.world.get::<Transform>(entity).unwrap();
+        assert!(transform.translation.distance(Vec3::new(199., 0., 0.)) < 1e-4);
     }
 }
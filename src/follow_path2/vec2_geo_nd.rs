@@ -10,7 +10,7 @@ pub(crate) struct Vec2Geo(pub(crate) Vec2);
 
 impl Clone for Vec2Geo {
     fn clone(&self) -> Self {
-        Self(self.0)
+        *self
     }
 }
 
@@ -1,5 +1,6 @@
 /// Module for following a preset 3D Path
 pub mod components;
+pub mod events;
 pub mod path;
 pub mod plugin;
 pub mod systems;
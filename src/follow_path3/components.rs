@@ -1,17 +1,211 @@
 use crate::follow_path3::path::Path3;
+use crate::traversal::{Dwell, OnFinish, PathDirection};
 use bevy::prelude::*;
+use std::f32::consts::FRAC_PI_4;
+
+/// How a 3D follower is rotated as it moves along its path
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum RotationMode {
+    /// Leave the rotation alone, so other systems can control it
+    None,
+    /// Face the direction of travel
+    #[default]
+    FaceDirection,
+    /// Face the point the given distance further along the path
+    LookAhead(f32),
+    /// Keep the given rotation
+    Fixed(Quat),
+}
+
+/// How a 3D follower rolls into bends, leaning towards their inside
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Banking {
+    /// Bank angle per unit of curvature and speed, in radians
+    pub strength: f32,
+    /// Largest angle to bank by, in radians
+    pub max_angle: f32,
+    /// Rate at which the bank angle follows the bends, per second
+    ///
+    /// Higher rates follow faster, and an infinite rate follows immediately.
+    pub smoothing: f32,
+}
+
+impl Default for Banking {
+    fn default() -> Self {
+        Self {
+            strength: 1.,
+            max_angle: FRAC_PI_4,
+            smoothing: f32::INFINITY,
+        }
+    }
+}
 
 /// Component that follows a 3D path at a specific speed
 #[derive(Component)]
 pub struct FollowPath3 {
-    /// The [Path](Path3) to follow
-    pub path: Path3,
-    /// The index of the current point the entity is moving to
-    pub cur_target: usize,
-    /// Speed at which the entity moves
+    /// Handle to the [Path](Path3) to follow
+    pub path: Handle<Path3>,
+    /// Distance the entity has travelled along the path
+    pub distance: f32,
+    /// Length of the path when the entity last moved along it
+    ///
+    /// When the path changes length, like when its file is reloaded, the
+    /// [distance](FollowPath3::distance) is scaled along with it to keep the entity's progress.
+    /// Reset it to [None] when switching to another path to keep the distance as it is.
+    pub path_length: Option<f32>,
+    /// Speed at which the entity moves, in units per second
+    ///
+    /// Ignored while the path has [speed keys](Path3::speed_keys).
     pub speed: f32,
-    /// Sets the epsilon for detecting when a point has been reached
-    pub epsilon: f32,
-    /// Upwards pointing axis of the entity
+    /// Speed the entity is moving at right now, as it accelerates towards or brakes from
+    /// [speed](FollowPath3::speed)
+    pub current_speed: f32,
+    /// Maximum increase of the current speed, in units per second squared
+    pub max_acceleration: f32,
+    /// Maximum decrease of the current speed, in units per second squared
+    ///
+    /// When finite, the entity brakes ahead of the points it has to stop at, like the end of the path.
+    pub max_deceleration: f32,
+    /// Maximum acceleration towards the inside of bends, in units per second squared
+    ///
+    /// When finite, the entity slows down for sharp bends in the path. Without a
+    /// [deceleration limit](FollowPath3::max_deceleration), it brakes for them at this rate.
+    pub max_lateral_acceleration: f32,
+    /// Distance to keep to the right of (X) and above (Y) the path, measured from its centreline
+    /// in the path's [frame](Path3::orientation_at)
+    ///
+    /// Right is the same side as for
+    /// [FollowPath2::offset](crate::follow_path2::components::FollowPath2::offset). Negative
+    /// distances are on the other side.
+    pub offset: Vec2,
+    /// Offset the entity is at right now, as it moves towards [offset](FollowPath3::offset)
+    pub current_offset: Vec2,
+    /// Maximum change of the current offset, in units per second
+    pub offset_speed: f32,
+    /// Direction in which the entity moves along the path
+    pub direction: PathDirection,
+    /// Whether the entity is held in place
+    ///
+    /// Paused entities still move along with their [anchor](FollowPath3::anchor).
+    pub paused: bool,
+    /// Entity the path is relative to, like a moving platform
+    ///
+    /// The path's points are in the anchor's space, and the entity follows them wherever the
+    /// anchor goes. Without an anchor, the points are in the space of the entity's parent.
+    pub anchor: Option<Entity>,
+    /// Multiplier for the time that passes for the entity
+    pub time_scale: f32,
+    /// Set while the entity waits at a waypoint
+    pub dwell: Option<Dwell>,
+    /// What to do once the end of the path has been reached
+    pub on_finish: OnFinish,
+    /// Number of cycles of the [path's mode](Path3::mode) completed so far
+    pub cycles_completed: u32,
+    /// How the entity is rotated as it moves
+    pub rotation_mode: RotationMode,
+    /// Whether and how the entity rolls into bends
+    ///
+    /// Only applies while the entity faces along the path.
+    pub banking: Option<Banking>,
+    /// Angle the entity is currently rolled by around its forward axis, in radians
+    ///
+    /// Positive angles lean to the right.
+    pub bank_angle: f32,
+    /// Upwards pointing axis of the entity at the start of the path
+    ///
+    /// Further along, the entity's up axis follows the path's
+    /// [rotation-minimizing frame](Path3::frame_at), so it does not flip on steep sections.
+    /// Ignored on paths with [twists](Path3::twists).
     pub up_axis: Vec3,
 }
+
+impl FollowPath3 {
+    /// Create the component, starting at the first point of *path* and moving at *speed* units per second
+    pub fn new(path: Handle<Path3>, speed: f32) -> Self {
+        Self {
+            path,
+            distance: 0.,
+            path_length: None,
+            speed,
+            current_speed: 0.,
+            max_acceleration: f32::INFINITY,
+            max_deceleration: f32::INFINITY,
+            max_lateral_acceleration: f32::INFINITY,
+            offset: Vec2::ZERO,
+            current_offset: Vec2::ZERO,
+            offset_speed: f32::INFINITY,
+            direction: PathDirection::default(),
+            paused: false,
+            anchor: None,
+            time_scale: 1.,
+            dwell: None,
+            on_finish: OnFinish::default(),
+            cycles_completed: 0,
+            rotation_mode: RotationMode::default(),
+            banking: None,
+            bank_angle: 0.,
+            up_axis: Vec3::Y,
+        }
+    }
+
+    /// Move the [current offset](FollowPath3::current_offset) towards the
+    /// [offset](FollowPath3::offset) over *delta* seconds
+    pub(crate) fn shift(&mut self, delta: f32) {
+        let difference = self.offset - self.current_offset;
+        if self.offset_speed.is_infinite() {
+            self.current_offset = self.offset;
+        } else {
+            self.current_offset += difference.clamp_length_max(self.offset_speed * delta);
+        }
+    }
+
+    /// Roll the [bank angle](FollowPath3::bank_angle) towards leaning into a bend of
+    /// *curvature*, to the right if positive, over *delta* seconds
+    pub(crate) fn bank(&mut self, curvature: f32, delta: f32) {
+        let target = match self.banking {
+            Some(banking) => (banking.strength * curvature * self.current_speed)
+                .clamp(-banking.max_angle, banking.max_angle),
+            None => 0.,
+        };
+        let smoothing = self
+            .banking
+            .map_or(f32::INFINITY, |banking| banking.smoothing);
+        if smoothing.is_infinite() {
+            self.bank_angle = target;
+        } else {
+            self.bank_angle += (target - self.bank_angle) * (1. - (-smoothing * delta).exp());
+        }
+    }
+
+    /// Turn around and move along the path in the other direction
+    pub fn reverse(&mut self) {
+        self.direction = self.direction.reversed();
+    }
+
+    /// How far along *path* the entity is, from 0 at the start to 1 at the end
+    pub fn progress(&self, path: &Path3) -> f32 {
+        let length = path.length();
+        if length > 0. {
+            (self.distance / length).clamp(0., 1.)
+        } else {
+            0.
+        }
+    }
+
+    /// Move the entity to *fraction* of the length of *path*, from 0 to 1
+    pub fn seek_fraction(&mut self, path: &Path3, fraction: f32) {
+        self.distance = fraction * path.length();
+    }
+
+    /// Number of cycles left before *path* is finished, or [None] if it never finishes
+    pub fn cycles_left(&self, path: &Path3) -> Option<u32> {
+        path.mode
+            .cycles()
+            .map(|cycles| cycles.saturating_sub(self.cycles_completed))
+    }
+
+    /// The index of the point of *path* the entity is currently moving to
+    pub fn next_point(&self, path: &Path3) -> Option<usize> {
+        path.segment_at(self.distance).map(|location| location.end)
+    }
+}
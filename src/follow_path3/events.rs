@@ -0,0 +1,24 @@
+use crate::follow_path3::path::Path3;
+use bevy::prelude::*;
+
+/// Event sent when an entity reaches the end of a [Path](Path3) that does not loop
+pub struct PathFinished {
+    /// The entity that finished the path
+    pub entity: Entity,
+    /// Handle to the [Path](Path3) that was finished
+    pub path: Handle<Path3>,
+}
+
+/// Event sent when an entity passes one of the [waypoints](Path3::waypoints) of its path
+pub struct WaypointReached {
+    /// The entity that reached the waypoint
+    pub entity: Entity,
+    /// Index of the waypoint within [Path3::waypoints]
+    pub waypoint: usize,
+    /// Index of the waypoint's point within [Path3::points]
+    pub point: usize,
+    /// Whether reaching the waypoint completed a loop around the path
+    pub completed_loop: bool,
+    /// The [tag](crate::follow_path3::path::WaypointAction::tag) of the waypoint
+    pub tag: Option<String>,
+}
@@ -1,4 +1,10 @@
 use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use std::f32::consts::{PI, TAU};
+
+use crate::arc_length::{ArcLengths, SegmentLocation};
+use crate::speed::{cornering_speed, interpolate_keys, resolve_keys, KeyPosition, SpeedKey};
+use crate::traversal::{self, Advance, PathDirection, PathMode};
 
 use crate::follow_path3::path::PathSegment::{CubicBezierCurve, Point, QuadraticBezierCurve};
 use crate::follow_path3::vec3_geo_nd::Vec3Geo;
@@ -8,36 +14,578 @@ use bevy::render::mesh::PrimitiveTopology;
 
 type Bezier3 = bezier_nd::Bezier<f32, Vec3Geo, 3>;
 
+/// Smallest cosine of half the angle of a bend that [offsets](Path3::offset_at) are scaled for,
+/// limiting how far they stick out at sharp corners
+const MIN_MITER_COSINE: f32 = 0.25;
+
 /// Possible segments to build a 3D path from
 ///
 /// The last point of the segment will always connect to the first point of the next.
-pub(crate) enum PathSegment {
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PathSegment {
+    /// A single point, connected to the previous one by a line
     Point(Vec3),
     /// Points of a cubic bezier curve, with 2 control points.
     CubicBezierCurve {
+        /// End point of the curve
         to: Vec3,
+        /// Control point near the start of the curve
         ctrl1: Vec3,
+        /// Control point near the end of the curve
         ctrl2: Vec3,
+        /// How close to straight lines the curve is split into, lower values giving more points
         straightness: f32,
     },
     /// Points of a quadratic bezier curve, with a single control point
     QuadraticBezierCurve {
+        /// End point of the curve
         to: Vec3,
+        /// Control point of the curve
         ctrl: Vec3,
+        /// How close to straight lines the curve is split into, lower values giving more points
         straightness: f32,
     },
 }
 
+/// Actions a follower carries out when it reaches a waypoint
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct WaypointAction {
+    /// Seconds to wait at the waypoint before moving on
+    pub wait: f32,
+    /// Rotation to take while waiting at the waypoint
+    pub facing: Option<Quat>,
+    /// Custom tag that is sent along with the
+    /// [WaypointReached](crate::follow_path3::events::WaypointReached) event
+    pub tag: Option<String>,
+}
+
+/// How a [Path3] is rolled around its direction at one of its points
+///
+/// Roll angles between twisted points are interpolated linearly.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Twist {
+    /// Direction the path's up axis should point in, as far as it can while staying
+    /// perpendicular to the path
+    ///
+    /// The path rolls the short way around from the previous twist to reach it.
+    Up(Vec3),
+    /// Angle in radians to roll the path's [frame](Path3::frame_at) by, to the right if positive
+    ///
+    /// Unlike [up directions](Twist::Up), rolls can turn by more than half a turn between points,
+    /// like in a corkscrew.
+    Roll(f32),
+}
+
 /// Contains the data for the path to follow
-#[derive(Default, Clone)]
+///
+/// Paths are [assets](bevy::asset::Assets), so followers on the same route can share one.
+#[derive(Default, Clone, TypeUuid)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "Path3Data", into = "Path3Data"))]
+#[uuid = "b2d491fc-6ec4-4178-84aa-704ff0eec17e"]
 pub struct Path3 {
     /// The list of [Points](bevy::math::f32::Vec3) to follow
-    pub points: Vec<Vec3>,
-    /// Whether the path circles back to the first point, or not.
-    pub is_loop: bool,
+    points: Vec<Vec3>,
+    /// Distances along the points, kept in sync with them
+    lengths: ArcLengths,
+    /// Curvature at each point, as if the path was closed
+    curvatures: Vec<f32>,
+    /// Direction towards the inside of the bend at each point, as if the path was closed
+    bends: Vec<Vec3>,
+    /// Rotation-minimizing frame of the segment starting at each point, as if the path was open
+    frames: Vec<Quat>,
+    /// Rotation-minimizing frame of the segment starting at each point, as if the path was closed
+    closed_frames: Vec<Quat>,
+    /// Twist the closed frames pick up going around the path once
+    closing_twist: f32,
+    /// Twists at some of the points, by index
+    twists: Vec<(usize, Twist)>,
+    /// Distances and roll angles of the twists, as if the path was open
+    roll_keys: Vec<(f32, f32)>,
+    /// Distances and roll angles of the twists, as if the path was closed
+    closed_roll_keys: Vec<(f32, f32)>,
+    /// Indices of the points that are waypoints
+    waypoints: Vec<usize>,
+    /// Actions for each waypoint
+    actions: Vec<WaypointAction>,
+    /// Positions within the waypoints of those that have to be waited at, in order
+    waiting: Vec<usize>,
+    /// Keys for the speed along the path
+    speed_keys: Vec<SpeedKey>,
+    /// Distances and speeds of the speed keys, sorted along the path
+    resolved_speed_keys: Vec<(f32, f32)>,
+    /// How the path is traversed, and whether it circles back to the first point
+    pub mode: PathMode,
 }
 
 impl Path3 {
+    /// Create a path through the given [points](bevy::math::f32::Vec3)
+    pub fn new(points: Vec<Vec3>, mode: PathMode) -> Self {
+        let lengths = ArcLengths::new(&points, |a, b| a.distance(*b));
+        let curvatures = vertex_curvatures(&points);
+        let bends = vertex_bends(&points);
+        let (frames, _) = transport_frames(&points, false);
+        let (closed_frames, closing_twist) = transport_frames(&points, true);
+        let waypoints = (0..points.len()).collect();
+        let actions = vec![WaypointAction::default(); points.len()];
+        Self {
+            points,
+            lengths,
+            curvatures,
+            bends,
+            frames,
+            closed_frames,
+            closing_twist,
+            twists: Vec::new(),
+            roll_keys: Vec::new(),
+            closed_roll_keys: Vec::new(),
+            waypoints,
+            actions,
+            waiting: Vec::new(),
+            speed_keys: Vec::new(),
+            resolved_speed_keys: Vec::new(),
+            mode,
+        }
+    }
+
+    /// The list of [points](bevy::math::f32::Vec3) to follow
+    pub fn points(&self) -> &[Vec3] {
+        &self.points
+    }
+
+    /// Indices of the [points](Path3::points) that are waypoints
+    ///
+    /// Paths built by a [PathBuilder] only mark the ends of its segments as waypoints, while
+    /// paths created from a list of points treat every point as a waypoint.
+    pub fn waypoints(&self) -> &[usize] {
+        &self.waypoints
+    }
+
+    /// Set which [points](Path3::points) are waypoints, clearing their actions
+    ///
+    /// The waypoints are kept sorted along the path, without duplicates. Panics if an index is out
+    /// of bounds.
+    pub fn set_waypoints(&mut self, mut waypoints: Vec<usize>) {
+        assert!(
+            waypoints.iter().all(|index| *index < self.points.len()),
+            "Waypoint index out of bounds"
+        );
+        waypoints.sort_unstable();
+        waypoints.dedup();
+        self.actions = vec![WaypointAction::default(); waypoints.len()];
+        self.waiting.clear();
+        self.waypoints = waypoints;
+        self.resolved_speed_keys = resolve_keys(&self.speed_keys, &self.lengths, &self.waypoints);
+    }
+
+    /// Keys for the speed followers move at along the path
+    pub fn speed_keys(&self) -> &[SpeedKey] {
+        &self.speed_keys
+    }
+
+    /// Set the keys for the speed followers move at along the path
+    ///
+    /// While a path has speed keys, followers use them instead of their own speed. Keys
+    /// referring to waypoints that do not exist are ignored.
+    pub fn set_speed_keys(&mut self, speed_keys: Vec<SpeedKey>) {
+        self.resolved_speed_keys = resolve_keys(&speed_keys, &self.lengths, &self.waypoints);
+        self.speed_keys = speed_keys;
+    }
+
+    /// Get the speed interpolated from the [speed keys](Path3::speed_keys) at *distance*
+    ///
+    /// Returns [None] if the path has no speed keys.
+    pub fn speed_at(&self, distance: f32) -> Option<f32> {
+        let closed = self.mode.is_closed();
+        interpolate_keys(
+            &self.resolved_speed_keys,
+            self.lengths.normalize(distance, closed),
+            self.length(),
+            closed,
+        )
+    }
+
+    /// The [actions](WaypointAction) of the waypoint at *waypoint* within
+    /// [waypoints](Path3::waypoints)
+    ///
+    /// Panics if *waypoint* is out of bounds.
+    pub fn waypoint_action(&self, waypoint: usize) -> &WaypointAction {
+        &self.actions[waypoint]
+    }
+
+    /// Set the [actions](WaypointAction) of the waypoint at *waypoint* within
+    /// [waypoints](Path3::waypoints)
+    ///
+    /// Panics if *waypoint* is out of bounds.
+    pub fn set_waypoint_action(&mut self, waypoint: usize, action: WaypointAction) {
+        match (self.waiting.binary_search(&waypoint), action.wait > 0.) {
+            (Ok(position), false) => {
+                self.waiting.remove(position);
+            }
+            (Err(position), true) => self.waiting.insert(position, waypoint),
+            _ => {}
+        }
+        self.actions[waypoint] = action;
+    }
+
+    /// Move *distance* by *travel* along the path in *direction*, stopping at waypoints that have
+    /// to be waited at
+    pub(crate) fn advance(
+        &self,
+        distance: &mut f32,
+        travel: f32,
+        direction: PathDirection,
+        cycles: &mut u32,
+    ) -> Advance {
+        traversal::advance(
+            &self.lengths,
+            &self.waypoints,
+            |waypoint| self.actions[waypoint].wait,
+            self.mode,
+            distance,
+            travel,
+            direction == PathDirection::Forward,
+            cycles,
+        )
+    }
+
+    /// Distance that can be travelled from *distance* in *direction* before having to stop
+    pub(crate) fn distance_to_stop(
+        &self,
+        distance: f32,
+        direction: PathDirection,
+        cycles: u32,
+    ) -> f32 {
+        traversal::distance_to_stop(
+            &self.lengths,
+            &self.waypoints,
+            &self.waiting,
+            self.mode,
+            distance,
+            direction == PathDirection::Forward,
+            cycles,
+        )
+    }
+
+    /// [Twists](Twist) at some of the [points](Path3::points), by the index of the point
+    pub fn twists(&self) -> &[(usize, Twist)] {
+        &self.twists
+    }
+
+    /// Set the [twists](Twist) at some of the [points](Path3::points), by the index of the point
+    ///
+    /// While a path has twists, followers take their up axis from them instead of their own.
+    /// Panics if an index is out of bounds.
+    pub fn set_twists(&mut self, mut twists: Vec<(usize, Twist)>) {
+        assert!(
+            twists.iter().all(|(index, _)| *index < self.points.len()),
+            "Twist index out of bounds"
+        );
+        twists.sort_by_key(|(index, _)| *index);
+        self.twists = twists;
+        self.roll_keys = self.resolve_twists(false);
+        self.closed_roll_keys = self.resolve_twists(true);
+    }
+
+    /// Roll angles of the [twists](Path3::twists) relative to the frames, by distance along the
+    /// path
+    ///
+    /// On closed paths, the keys wrap around the ends so they can be interpolated as they are.
+    fn resolve_twists(&self, closed: bool) -> Vec<(f32, f32)> {
+        let mut keys: Vec<(f32, f32)> = Vec::new();
+        for (index, twist) in &self.twists {
+            let distance = self.lengths.distance_to(*index);
+            let roll = match twist {
+                Twist::Roll(roll) => *roll,
+                Twist::Up(up) => {
+                    let roll = match roll_towards(self.frame(distance, closed), *up) {
+                        Some(roll) => roll,
+                        None => continue,
+                    };
+                    // Take the short way around from the previous twist
+                    let previous = keys.last().map_or(0., |(_, previous)| *previous);
+                    previous + (roll - previous + PI).rem_euclid(TAU) - PI
+                }
+            };
+            keys.push((distance, roll));
+        }
+        if let (true, Some(first), Some(last)) = (closed, keys.first(), keys.last()) {
+            let (first, last) = (*first, *last);
+            let length = self.lengths.length(true);
+            // Whole turns from the last twist around to the first
+            let turns = ((last.1 - first.1) / TAU).round() * TAU;
+            keys.insert(0, (last.0 - length, last.1 - turns));
+            keys.push((first.0 + length, first.1 + turns));
+        }
+        keys
+    }
+
+    /// Get the roll angle interpolated from the [twists](Path3::twists) at *distance*
+    ///
+    /// Returns [None] if the path has no twists.
+    pub fn roll_at(&self, distance: f32) -> Option<f32> {
+        let closed = self.mode.is_closed();
+        let keys = if closed {
+            &self.closed_roll_keys
+        } else {
+            &self.roll_keys
+        };
+        interpolate_keys(
+            keys,
+            self.lengths.normalize(distance, closed),
+            self.length(),
+            false,
+        )
+    }
+
+    /// Replace the points of the path, making every point a waypoint and clearing its
+    /// [twists](Path3::twists)
+    pub fn set_points(&mut self, points: Vec<Vec3>) {
+        let speed_keys = std::mem::take(&mut self.speed_keys);
+        *self = Self::new(points, self.mode);
+        self.set_speed_keys(speed_keys);
+    }
+
+    /// Total length of the path, including the segment back to the first point on closed paths
+    pub fn length(&self) -> f32 {
+        self.lengths.length(self.mode.is_closed())
+    }
+
+    /// Distance along the path from the first point to the point at *index*
+    ///
+    /// Panics if *index* is out of bounds.
+    pub fn distance_to_point(&self, index: usize) -> f32 {
+        self.lengths.distance_to(index)
+    }
+
+    /// Find the segment at *distance* along the path
+    ///
+    /// Distances wrap around [closed](PathMode::is_closed) paths and are clamped to the ends of
+    /// other paths.
+    /// Returns [None] if the path has no points.
+    pub fn segment_at(&self, distance: f32) -> Option<SegmentLocation> {
+        self.lengths.locate(distance, self.mode.is_closed())
+    }
+
+    /// Get the [point](bevy::math::f32::Vec3) at *distance* along the path
+    ///
+    /// Returns [None] if the path has no points.
+    pub fn sample_at_distance(&self, distance: f32) -> Option<Vec3> {
+        self.segment_at(distance).map(|location| {
+            self.points[location.start].lerp(self.points[location.end], location.fraction)
+        })
+    }
+
+    /// Get the normalized direction of the segment at *distance* along the path
+    ///
+    /// Returns [None] if the path has no points or the segment has no length.
+    pub fn direction_at(&self, distance: f32) -> Option<Vec3> {
+        self.segment_at(distance).and_then(|location| {
+            (self.points[location.end] - self.points[location.start]).try_normalize()
+        })
+    }
+
+    /// Get the rotation of the path's rotation-minimizing frame at *distance*
+    ///
+    /// The frame's -Z axis points along the segment and its Y axis is the path's normal, which
+    /// starts out as close to +Y as possible and turns as little as it can from there. As the Z
+    /// axis points backwards, rolling the frame to the right is a negative rotation around it. On
+    /// [closed](PathMode::is_closed) paths, the twist needed to line the frame up with itself
+    /// again is spread evenly over the path.
+    /// Returns [None] if the path has no points.
+    pub fn frame_at(&self, distance: f32) -> Option<Quat> {
+        self.segment_at(distance)
+            .map(|_| self.frame(distance, self.mode.is_closed()))
+    }
+
+    /// Get the [frame](Path3::frame_at) at *distance*, as if the path was *closed* or not
+    ///
+    /// Panics if the path has no points.
+    fn frame(&self, distance: f32, closed: bool) -> Quat {
+        let location = self
+            .lengths
+            .locate(distance, closed)
+            .expect("Path has no points");
+        if !closed {
+            return self.frames[location.start];
+        }
+        let length = self.lengths.length(closed);
+        let untwist = if length > 0. {
+            self.closing_twist * self.lengths.normalize(distance, closed) / length
+        } else {
+            0.
+        };
+        self.closed_frames[location.start] * Quat::from_rotation_z(untwist)
+    }
+
+    /// Get the [frame](Path3::frame_at) at *distance*, rolled by the path's
+    /// [twists](Path3::twists)
+    ///
+    /// Paths without twists are rolled so the frame's Y axis at the start of the path is as close
+    /// as possible to *up* instead.
+    /// Returns [None] if the path has no points.
+    pub fn orientation_at(&self, distance: f32, up: Vec3) -> Option<Quat> {
+        let roll = match self.roll_at(distance) {
+            Some(roll) => roll,
+            None => roll_towards(self.frame_at(0.)?, up).unwrap_or(0.),
+        };
+        self.frame_at(distance)
+            .map(|frame| frame * Quat::from_rotation_z(-roll))
+    }
+
+    /// Get the [point](bevy::math::f32::Vec3) at *distance* along the path, moved *offset* units
+    /// to the right of (X) and above (Y) it in the path's [orientation](Path3::orientation_at)
+    ///
+    /// The offset path runs parallel to the segments, with its corners on the bisectors of the
+    /// bends.
+    /// Returns [None] if the path has no points.
+    pub fn offset_at(&self, distance: f32, offset: Vec2, up: Vec3) -> Option<Vec3> {
+        let location = self.segment_at(distance)?;
+        let start = self.offset_point(location.start, offset, up);
+        let end = self.offset_point(location.end, offset, up);
+        Some(start.lerp(end, location.fraction))
+    }
+
+    /// The point at *index*, moved by *offset* in the orientation halfway between the segments on
+    /// either side
+    fn offset_point(&self, index: usize, offset: Vec2, up: Vec3) -> Vec3 {
+        let point = self.points[index];
+        if offset == Vec2::ZERO {
+            return point;
+        }
+        let closed = self.mode.is_closed();
+        let frame = self.point_frame(index, closed);
+        let roll = match self.roll_at(self.lengths.distance_to(index)) {
+            Some(roll) => roll,
+            None => roll_towards(self.frame(0., closed), up).unwrap_or(0.),
+        };
+        let shift = frame * Quat::from_rotation_z(-roll) * offset.extend(0.);
+        let last = self.points.len() - 1;
+        if !closed && (index == 0 || index == last) {
+            return point + shift;
+        }
+        // Moving further out towards the outside of bends keeps the offset parallel to the
+        // segments
+        let segment = self.frame(self.lengths.distance_to(index), closed) * Vec3::NEG_Z;
+        let cosine = (frame * Vec3::NEG_Z).dot(segment).max(MIN_MITER_COSINE);
+        let bend = self.bends[index];
+        point + shift + bend * bend.dot(shift) * (1. / cosine - 1.)
+    }
+
+    /// Get the [frame](Path3::frame_at) at the point at *index*, as if the path was *closed* or
+    /// not, halfway between the frames of the segments on either side
+    fn point_frame(&self, index: usize, closed: bool) -> Quat {
+        let frames = if closed {
+            &self.closed_frames
+        } else {
+            &self.frames
+        };
+        let length = self.lengths.length(closed);
+        let untwist = |distance: f32| {
+            if closed && length > 0. {
+                Quat::from_rotation_z(self.closing_twist * distance / length)
+            } else {
+                Quat::IDENTITY
+            }
+        };
+        let distance = self.lengths.distance_to(index);
+        let outgoing = frames[index] * untwist(distance);
+        let incoming = match index {
+            0 if closed => frames[frames.len() - 1] * untwist(length),
+            0 => outgoing,
+            _ => frames[index - 1] * untwist(distance),
+        };
+        incoming.slerp(outgoing, 0.5)
+    }
+
+    /// Get the curvature of the path at *distance*, interpolated between its points
+    ///
+    /// The ends of paths that are not [closed](PathMode::is_closed) are treated as straight.
+    pub fn curvature_at(&self, distance: f32) -> f32 {
+        let closed = self.mode.is_closed();
+        let last = self.points.len().saturating_sub(1);
+        let curvature = |index: usize| {
+            if !closed && (index == 0 || index == last) {
+                0.
+            } else {
+                self.curvatures[index]
+            }
+        };
+        self.segment_at(distance).map_or(0., |location| {
+            let start = curvature(location.start);
+            start + (curvature(location.end) - start) * location.fraction
+        })
+    }
+
+    /// Get the curvature of the path at *distance* as a vector pointing towards the inside of the
+    /// bend, interpolated between its points
+    ///
+    /// The ends of paths that are not [closed](PathMode::is_closed) are treated as straight.
+    pub fn bend_at(&self, distance: f32) -> Vec3 {
+        let closed = self.mode.is_closed();
+        let last = self.points.len().saturating_sub(1);
+        let bend = |index: usize| {
+            if !closed && (index == 0 || index == last) {
+                Vec3::ZERO
+            } else {
+                self.bends[index] * self.curvatures[index]
+            }
+        };
+        self.segment_at(distance).map_or(Vec3::ZERO, |location| {
+            bend(location.start).lerp(bend(location.end), location.fraction)
+        })
+    }
+
+    /// Highest speed at *distance* from which a follower moving in *direction* can slow down in
+    /// time for the bends ahead
+    pub(crate) fn cornering_speed(
+        &self,
+        distance: f32,
+        direction: PathDirection,
+        max_lateral_acceleration: f32,
+        deceleration: f32,
+        max_speed: f32,
+    ) -> f32 {
+        cornering_speed(
+            &self.lengths,
+            &self.curvatures,
+            self.mode.is_closed(),
+            distance,
+            direction == PathDirection::Forward,
+            max_lateral_acceleration,
+            deceleration,
+            max_speed,
+        )
+    }
+
+    /// Get the [point](bevy::math::f32::Vec3) at *fraction* of the path's length, from 0 to 1
+    ///
+    /// Returns [None] if the path has no points.
+    pub fn sample_at_fraction(&self, fraction: f32) -> Option<Vec3> {
+        self.sample_at_distance(fraction * self.length())
+    }
+
+    /// Spawn a [bundle](PbrBundle) for drawing the path as a child of *anchor*, so it moves along
+    /// with it
+    ///
+    /// Returns the Entity for the mesh
+    #[cfg(feature = "debug_draw")]
+    pub fn spawn_anchored_mesh(
+        &self,
+        commands: &mut Commands,
+        meshes: &mut ResMut<Assets<Mesh>>,
+        color: Color,
+        anchor: Entity,
+    ) -> Entity {
+        let mesh = self.spawn_mesh(commands, meshes, color);
+        commands.entity(anchor).add_child(mesh);
+        mesh
+    }
+
     /// Spawn a [bundle](PbrBundle) for drawing the path
     ///
     /// Returns the Entity for the mesh
@@ -50,7 +598,7 @@ impl Path3 {
     ) -> Entity {
         let mut mesh = Mesh::new(PrimitiveTopology::LineStrip);
         let mut points: Vec<[f32; 3]> = self.points.iter().map(|p| [p.x, p.y, p.z]).collect();
-        if self.is_loop {
+        if self.mode.is_closed() {
             points.push(points[0]);
         }
 
@@ -79,9 +627,205 @@ impl Path3 {
     }
 }
 
+/// Curvature at every point, as if the path was closed
+fn vertex_curvatures(points: &[Vec3]) -> Vec<f32> {
+    let count = points.len();
+    (0..count)
+        .map(|index| {
+            if count < 3 {
+                return 0.;
+            }
+            let incoming = points[index] - points[(index + count - 1) % count];
+            let outgoing = points[(index + 1) % count] - points[index];
+            // The angle turned at the point, spread over the segments on either side
+            let curvature = incoming.angle_between(outgoing).abs()
+                / ((incoming.length() + outgoing.length()) / 2.);
+            if curvature.is_finite() {
+                curvature
+            } else {
+                0.
+            }
+        })
+        .collect()
+}
+
+/// Angle to roll *frame* by, to the right if positive, so its Y axis comes as close to *up* as
+/// possible
+///
+/// Returns [None] if *up* points along the frame's direction.
+fn roll_towards(frame: Quat, up: Vec3) -> Option<f32> {
+    let tangent = frame * Vec3::NEG_Z;
+    let normal = frame * Vec3::Y;
+    (up - tangent * tangent.dot(up))
+        .try_normalize()
+        .map(|up| tangent.dot(normal.cross(up)).atan2(normal.dot(up)))
+}
+
+/// Direction towards the inside of the bend at every point, as if the path was closed
+///
+/// Points without a bend get a zero vector.
+fn vertex_bends(points: &[Vec3]) -> Vec<Vec3> {
+    let count = points.len();
+    (0..count)
+        .map(|index| {
+            let incoming = (points[index] - points[(index + count - 1) % count]).try_normalize();
+            let outgoing = (points[(index + 1) % count] - points[index]).try_normalize();
+            match (incoming, outgoing) {
+                (Some(incoming), Some(outgoing)) => {
+                    (outgoing - incoming).try_normalize().unwrap_or(Vec3::ZERO)
+                }
+                _ => Vec3::ZERO,
+            }
+        })
+        .collect()
+}
+
+/// Rotation-minimizing frame of the segment starting at every point, carried along the path
+/// by parallel transport, and the twist around the tangent it picks up going around the path once
+fn transport_frames(points: &[Vec3], closed: bool) -> (Vec<Quat>, f32) {
+    let count = points.len();
+    let segments = if closed {
+        count
+    } else {
+        count.saturating_sub(1)
+    };
+    let mut tangents: Vec<Option<Vec3>> = (0..count)
+        .map(|index| {
+            (index < segments)
+                .then(|| (points[(index + 1) % count] - points[index]).try_normalize())
+                .flatten()
+        })
+        .collect();
+    // Segments without a direction of their own, like the end of a path, take one from their
+    // neighbours
+    let mut previous = tangents.iter().flatten().next().copied();
+    for tangent in tangents.iter_mut() {
+        if tangent.is_none() {
+            *tangent = previous;
+        }
+        previous = *tangent;
+    }
+    let tangents: Vec<Vec3> = tangents
+        .into_iter()
+        .map(|tangent| tangent.unwrap_or(Vec3::NEG_Z))
+        .collect();
+
+    let perpendicular = |vector: Vec3, tangent: Vec3| {
+        (vector - tangent * tangent.dot(vector))
+            .try_normalize()
+            .unwrap_or_else(|| tangent.any_orthonormal_vector())
+    };
+    let mut normals: Vec<Vec3> = Vec::with_capacity(count);
+    for (index, tangent) in tangents.iter().enumerate() {
+        let normal = match index {
+            0 => perpendicular(Vec3::Y, *tangent),
+            _ => {
+                let turn = Quat::from_rotation_arc(tangents[index - 1], *tangent);
+                perpendicular(turn * normals[index - 1], *tangent)
+            }
+        };
+        normals.push(normal);
+    }
+
+    let twist = if closed && count > 1 {
+        let turn = Quat::from_rotation_arc(tangents[count - 1], tangents[0]);
+        let returned = turn * normals[count - 1];
+        tangents[0]
+            .dot(normals[0].cross(returned))
+            .atan2(normals[0].dot(returned))
+    } else {
+        0.
+    };
+
+    let frames = tangents
+        .iter()
+        .zip(normals)
+        .map(|(tangent, normal)| {
+            let back = -*tangent;
+            Quat::from_mat3(&Mat3::from_cols(normal.cross(back), normal, back))
+        })
+        .collect();
+    (frames, twist)
+}
+
+/// Serialized form of a [Path3], leaving out the tables derived from its points
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Path3Data {
+    points: Vec<Vec3>,
+    #[serde(default)]
+    mode: PathMode,
+    /// Every point is a waypoint if left out
+    #[serde(default)]
+    waypoints: Option<Vec<usize>>,
+    #[serde(default)]
+    actions: Vec<WaypointAction>,
+    #[serde(default)]
+    speed_keys: Vec<SpeedKey>,
+    #[serde(default)]
+    twists: Vec<(usize, Twist)>,
+}
+
+#[cfg(feature = "serde")]
+impl From<Path3> for Path3Data {
+    fn from(path: Path3) -> Self {
+        Self {
+            points: path.points,
+            mode: path.mode,
+            waypoints: Some(path.waypoints),
+            actions: path.actions,
+            speed_keys: path.speed_keys,
+            twists: path.twists,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<Path3Data> for Path3 {
+    type Error = String;
+
+    fn try_from(data: Path3Data) -> Result<Self, Self::Error> {
+        let mut path = Path3::new(data.points, data.mode);
+        if let Some(waypoints) = data.waypoints {
+            if waypoints.iter().any(|index| *index >= path.points.len()) {
+                return Err("Waypoint index out of bounds".to_string());
+            }
+            path.set_waypoints(waypoints);
+        }
+        if data.actions.len() > path.waypoints.len() {
+            return Err("More actions than waypoints".to_string());
+        }
+        for (waypoint, action) in data.actions.into_iter().enumerate() {
+            path.set_waypoint_action(waypoint, action);
+        }
+        path.set_speed_keys(data.speed_keys);
+        if data
+            .twists
+            .iter()
+            .any(|(index, _)| *index >= path.points.len())
+        {
+            return Err("Twist index out of bounds".to_string());
+        }
+        path.set_twists(data.twists);
+        Ok(path)
+    }
+}
+
 /// Builder to simplify making [paths](Path3) using segments that are connected to each other
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(try_from = "PathBuilderData", into = "PathBuilderData")
+)]
 pub struct PathBuilder {
     segments: Vec<PathSegment>,
+    /// Actions for the waypoint at the end of each segment
+    actions: Vec<WaypointAction>,
+    /// Keys for the speed along the path
+    speed_keys: Vec<SpeedKey>,
+    /// Twists at the end of each segment
+    twists: Vec<Option<Twist>>,
 }
 
 impl PathBuilder {
@@ -89,12 +833,61 @@ impl PathBuilder {
     pub fn new(start: Vec3) -> Self {
         Self {
             segments: vec![Point(start)],
+            actions: vec![WaypointAction::default()],
+            speed_keys: Vec::new(),
+            twists: vec![None],
         }
     }
 
+    /// The segments the path is built from, starting with a [Point](PathSegment::Point)
+    pub fn segments(&self) -> &[PathSegment] {
+        &self.segments
+    }
+
+    /// Add a segment without any waypoint actions or twist
+    fn push(&mut self, segment: PathSegment) {
+        self.segments.push(segment);
+        self.actions.push(WaypointAction::default());
+        self.twists.push(None);
+    }
+
     /// Add a line from the previous point to the passed [point](bevy::math::f32::Vec3)
     pub fn add_line_to(&mut self, point: Vec3) {
-        self.segments.push(Point(point));
+        self.push(Point(point));
+    }
+
+    /// Add a line from the previous point to the passed [point](bevy::math::f32::Vec3),
+    /// carrying out *action* when a follower reaches it
+    pub fn add_line_to_with_action(&mut self, point: Vec3, action: WaypointAction) {
+        self.add_line_to(point);
+        self.set_action(action);
+    }
+
+    /// Set the [actions](WaypointAction) for the end point of the last added segment
+    pub fn set_action(&mut self, action: WaypointAction) {
+        if let Some(last) = self.actions.last_mut() {
+            *last = action;
+        }
+    }
+
+    /// Set the speed followers move at when passing the end point of the last added segment
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed_keys.push(SpeedKey {
+            at: KeyPosition::Waypoint(self.segments.len() - 1),
+            speed,
+        });
+    }
+
+    /// Set the [twist](Twist) of the path at the end point of the last added segment
+    pub fn set_twist(&mut self, twist: Twist) {
+        if let Some(last) = self.twists.last_mut() {
+            *last = Some(twist);
+        }
+    }
+
+    /// Add a [key](SpeedKey) for the speed followers move at along the path
+    pub fn add_speed_key(&mut self, key: SpeedKey) {
+        self.speed_keys.push(key);
     }
 
     /// Add a bezier curve from the previous to the specified end [point](bevy::math::f32::Vec3)
@@ -106,7 +899,7 @@ impl PathBuilder {
         ctrl2: Vec3,
         straightness: f32,
     ) {
-        self.segments.push(CubicBezierCurve {
+        self.push(CubicBezierCurve {
             to,
             ctrl1,
             ctrl2,
@@ -117,7 +910,7 @@ impl PathBuilder {
     /// Add a bezier curve from the previous to the specified end [point](bevy::math::f32::Vec3)
     /// using a single control point and the given straightness
     pub fn add_quadratic_bezier_curve(&mut self, to: Vec3, ctrl: Vec3, straightness: f32) {
-        self.segments.push(QuadraticBezierCurve {
+        self.push(QuadraticBezierCurve {
             to,
             ctrl,
             straightness,
@@ -126,7 +919,13 @@ impl PathBuilder {
 
     /// Build a list of [points](bevy::math::f32::Vec3) from the current segments
     pub fn build_points(&self) -> Vec<Vec3> {
+        self.build_points_and_waypoints().0
+    }
+
+    /// Build the [points](bevy::math::f32::Vec3) and the indices of the ends of the segments
+    fn build_points_and_waypoints(&self) -> (Vec<Vec3>, Vec<usize>) {
         let mut path_points = Vec::new();
+        let mut waypoints = Vec::new();
 
         if let Some(segment) = self.segments.first() {
             let mut last_pos = match segment {
@@ -135,6 +934,8 @@ impl PathBuilder {
                     panic!("Path has to start with a Point")
                 }
             };
+            path_points.push(*last_pos);
+            waypoints.push(0);
 
             for segment in self.segments.iter().skip(1) {
                 match segment {
@@ -154,8 +955,11 @@ impl PathBuilder {
                             &Vec3Geo(*ctrl2),
                             &Vec3Geo(*to),
                         );
-                        let points: &mut Vec<Vec3> =
-                            &mut curve.as_points(*straightness).map(|p| p.0).collect();
+                        let points: &mut Vec<Vec3> = &mut curve
+                            .as_points(*straightness)
+                            .skip(1)
+                            .map(|p| p.0)
+                            .collect();
                         path_points.append(points);
                         last_pos = to;
                     }
@@ -166,32 +970,290 @@ impl PathBuilder {
                     } => {
                         let curve =
                             Bezier3::quadratic(&Vec3Geo(*last_pos), &Vec3Geo(*ctrl), &Vec3Geo(*to));
-                        let points: &mut Vec<Vec3> =
-                            &mut curve.as_points(*straightness).map(|p| p.0).collect();
+                        let points: &mut Vec<Vec3> = &mut curve
+                            .as_points(*straightness)
+                            .skip(1)
+                            .map(|p| p.0)
+                            .collect();
                         path_points.append(points);
                         last_pos = to;
                     }
                 }
+                waypoints.push(path_points.len() - 1);
             }
         }
-        path_points
+        (path_points, waypoints)
     }
 
     /// Build a non looping [Path](Path3) from the current segments
     pub fn build_path(&self) -> Path3 {
-        let points = self.build_points();
-        Path3 {
-            points,
-            is_loop: false,
-        }
+        self.build_path_with_mode(PathMode::Once)
     }
 
     /// Build a looping [Path](Path3) from the current segments
     pub fn build_looping_path(&self) -> Path3 {
-        let points = self.build_points();
-        Path3 {
-            points,
-            is_loop: true,
+        self.build_path_with_mode(PathMode::Loop)
+    }
+
+    /// Build a [Path](Path3) from the current segments that is traversed following *mode*
+    ///
+    /// [Closed](PathMode::is_closed) paths connect back to their first point by themselves, so if
+    /// the last segment ends on the first point, that end point and its waypoint are left out. The
+    /// first waypoint stands in for it: its action fills in whatever the first waypoint's action
+    /// leaves unset, and speed keys on it move to the first waypoint.
+    pub fn build_path_with_mode(&self, mode: PathMode) -> Path3 {
+        let (mut points, mut waypoints) = self.build_points_and_waypoints();
+        let mut actions = self.actions.clone();
+        let mut speed_keys = self.speed_keys.clone();
+        let mut twists = self.twists.clone();
+        if mode.is_closed() && points.len() > 1 && points.first() == points.last() {
+            points.pop();
+            waypoints.pop();
+            let end = waypoints.len();
+            if let Some(action) = actions.pop() {
+                let first = &mut actions[0];
+                if first.wait <= 0. {
+                    first.wait = action.wait;
+                }
+                first.facing = first.facing.or(action.facing);
+                first.tag = first.tag.take().or(action.tag);
+            }
+            for key in &mut speed_keys {
+                if key.at == KeyPosition::Waypoint(end) {
+                    key.at = KeyPosition::Waypoint(0);
+                }
+            }
+            if let Some(twist) = twists.pop().flatten() {
+                twists[0] = twists[0].or(Some(twist));
+            }
+        }
+        let mut path = Path3 {
+            waypoints,
+            waiting: (0..actions.len())
+                .filter(|waypoint| actions[*waypoint].wait > 0.)
+                .collect(),
+            actions,
+            ..Path3::new(points, mode)
+        };
+        path.set_speed_keys(speed_keys);
+        let twists = path
+            .waypoints
+            .iter()
+            .zip(twists)
+            .filter_map(|(index, twist)| twist.map(|twist| (*index, twist)))
+            .collect();
+        path.set_twists(twists);
+        path
+    }
+}
+
+/// Serialized form of a [PathBuilder], where actions and speed keys can be left out
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct PathBuilderData {
+    segments: Vec<PathSegment>,
+    #[serde(default)]
+    actions: Vec<WaypointAction>,
+    #[serde(default)]
+    speed_keys: Vec<SpeedKey>,
+    #[serde(default)]
+    twists: Vec<Option<Twist>>,
+}
+
+#[cfg(feature = "serde")]
+impl From<PathBuilder> for PathBuilderData {
+    fn from(builder: PathBuilder) -> Self {
+        Self {
+            segments: builder.segments,
+            actions: builder.actions,
+            speed_keys: builder.speed_keys,
+            twists: builder.twists,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<PathBuilderData> for PathBuilder {
+    type Error = String;
+
+    fn try_from(data: PathBuilderData) -> Result<Self, Self::Error> {
+        if !matches!(data.segments.first(), Some(Point(_))) {
+            return Err("Path has to start with a Point".to_string());
+        }
+        let count = data.segments.len();
+        let mut actions = data.actions;
+        if actions.len() > count {
+            return Err("More actions than segments".to_string());
         }
+        actions.resize(count, WaypointAction::default());
+        let mut twists = data.twists;
+        if twists.len() > count {
+            return Err("More twists than segments".to_string());
+        }
+        twists.resize(count, None);
+        Ok(Self {
+            segments: data.segments,
+            actions,
+            speed_keys: data.speed_keys,
+            twists,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A saddle shaped loop, whose frames twist over a lap
+    fn wavy_loop() -> Path3 {
+        let points = (0..100)
+            .map(|index| {
+                let angle = index as f32 / 100. * TAU;
+                Vec3::new(angle.cos() * 3., angle.sin() * 2., (2. * angle).sin() * 3.)
+            })
+            .collect();
+        Path3::new(points, PathMode::Loop)
+    }
+
+    #[test]
+    fn frames_stay_valid_on_vertical_climbs() {
+        let path = Path3::new(
+            vec![
+                Vec3::ZERO,
+                Vec3::new(0., 0., -1.),
+                Vec3::new(0., 5., -1.),
+                Vec3::new(0., 5., -2.),
+            ],
+            PathMode::Once,
+        );
+        for step in 0..=70 {
+            let orientation = path.orientation_at(step as f32 * 0.1, Vec3::Y).unwrap();
+            assert!(orientation.is_finite() && orientation.is_normalized());
+        }
+        let start = path.orientation_at(0., Vec3::Y).unwrap();
+        let expected = Transform::identity()
+            .looking_at(Vec3::NEG_Z, Vec3::Y)
+            .rotation;
+        assert!(start.angle_between(expected) < 1e-3);
+        let climbing = path.orientation_at(3., Vec3::Y).unwrap();
+        assert!((climbing * Vec3::NEG_Z).distance(Vec3::Y) < 1e-3);
+    }
+
+    #[test]
+    fn offsets_are_on_the_same_side_as_in_2d() {
+        use crate::follow_path2::path::Path2;
+
+        // Seen from above the XY plane, with Z up, like a 2D path
+        let points = [Vec2::ZERO, Vec2::new(10., 0.), Vec2::new(10., 10.)];
+        let path2 = Path2::new(points.to_vec(), PathMode::Once);
+        let path3 = Path3::new(
+            points.iter().map(|point| point.extend(0.)).collect(),
+            PathMode::Once,
+        );
+        for distance in [2., 10., 15.] {
+            let expected = path2.offset_at(distance, 1.5).unwrap();
+            let offset = path3.offset_at(distance, Vec2::X * 1.5, Vec3::Z).unwrap();
+            assert!(offset.distance(expected.extend(0.)) < 1e-4, "{:?}", offset);
+        }
+    }
+
+    #[test]
+    fn closed_frames_line_up_after_a_lap() {
+        let path = wavy_loop();
+        assert!(path.closing_twist.abs() > 0.1);
+        let length = path.length();
+        let start = path.frame_at(0.).unwrap();
+        let end = path.frame_at(length - 1e-3).unwrap();
+        // Turn the end frame onto the start's tangent, leaving only the roll between them
+        let end = Quat::from_rotation_arc(end * Vec3::NEG_Z, start * Vec3::NEG_Z) * end;
+        assert!(start.angle_between(end) < 1e-2);
+        assert!(path.frame_at(length).unwrap().angle_between(start) < 1e-3);
+    }
+
+    #[test]
+    fn closed_frames_change_smoothly() {
+        let path = wavy_loop();
+        let length = path.length();
+        let mut previous = path.frame_at(0.).unwrap();
+        for step in 1..=400 {
+            let frame = path.frame_at(length * step as f32 / 400.).unwrap();
+            assert!(previous.angle_between(frame) < 0.3);
+            previous = frame;
+        }
+    }
+
+    #[test]
+    fn rolls_are_interpolated_between_twists() {
+        let points = (0..5).map(|i| Vec3::new(0., 0., -(i as f32))).collect();
+        let mut path = Path3::new(points, PathMode::Once);
+        path.set_twists(vec![(4, Twist::Roll(PI)), (0, Twist::Up(Vec3::Y))]);
+        assert_eq!(path.roll_at(2.), Some(PI / 2.));
+        assert_eq!(path.roll_at(6.), Some(PI));
+        // Positive rolls turn the up axis to the right
+        let up = path.orientation_at(2., Vec3::Y).unwrap() * Vec3::Y;
+        assert!(up.distance(Vec3::X) < 1e-3, "{:?}", up);
+        let up = path.orientation_at(4., Vec3::Y).unwrap() * Vec3::Y;
+        assert!(up.distance(Vec3::NEG_Y) < 1e-3, "{:?}", up);
+
+        // Up directions are reached the short way around
+        let mut builder = PathBuilder::new(Vec3::ZERO);
+        builder.set_twist(Twist::Up(Vec3::Y));
+        builder.add_line_to(Vec3::new(0., 0., -4.));
+        builder.set_twist(Twist::Up(Vec3::NEG_X));
+        let up = builder.build_path().orientation_at(2., Vec3::Z).unwrap() * Vec3::Y;
+        let expected = Vec3::new(-1., 1., 0.).normalize();
+        assert!(up.distance(expected) < 1e-3, "{:?}", up);
+    }
+
+    #[test]
+    fn closed_paths_roll_across_their_ends() {
+        // A square with sides of 4, 16 long in all
+        let square = vec![
+            Vec3::ZERO,
+            Vec3::new(0., 0., -4.),
+            Vec3::new(4., 0., -4.),
+            Vec3::new(4., 0., 0.),
+        ];
+        let mut path = Path3::new(square, PathMode::Loop);
+        path.set_twists(vec![(0, Twist::Roll(0.5)), (2, Twist::Roll(1.5))]);
+        assert_eq!(path.roll_at(4.), Some(1.));
+        assert_eq!(path.roll_at(12.), Some(1.));
+        assert_eq!(path.roll_at(16.), Some(0.5));
+
+        // A corkscrew makes a whole turn from the first twist to the second, and keeps it going
+        // round to the first again
+        path.set_twists(vec![(0, Twist::Roll(0.)), (2, Twist::Roll(TAU))]);
+        assert_eq!(path.roll_at(4.), Some(PI));
+        assert_eq!(path.roll_at(12.), Some(TAU));
+        let start = path.orientation_at(0.01, Vec3::Y).unwrap();
+        let end = path.orientation_at(15.99, Vec3::Y).unwrap();
+        assert!((start * Vec3::Y).distance(end * Vec3::Y) < 0.01);
+
+        // A single twist rolls the whole path
+        path.set_twists(vec![(1, Twist::Roll(1.))]);
+        assert_eq!(path.roll_at(0.), Some(1.));
+        assert_eq!(path.roll_at(14.), Some(1.));
+    }
+
+    #[test]
+    fn closed_builders_move_their_end_to_the_first_waypoint() {
+        let mut builder = PathBuilder::new(Vec3::ZERO);
+        builder.add_line_to(Vec3::new(10., 0., 0.));
+        builder.add_line_to(Vec3::new(10., 0., -10.));
+        let home = WaypointAction {
+            wait: 2.,
+            facing: None,
+            tag: Some("home".to_string()),
+        };
+        builder.add_line_to_with_action(Vec3::ZERO, home.clone());
+        builder.set_speed(3.);
+        builder.set_twist(Twist::Roll(0.5));
+
+        let path = builder.build_looping_path();
+        assert_eq!(path.points().len(), 3);
+        assert_eq!(path.waypoint_action(0), &home);
+        assert_eq!(path.waiting, vec![0]);
+        assert_eq!(path.speed_at(0.), Some(3.));
+        assert_eq!(path.twists(), &[(0, Twist::Roll(0.5))]);
     }
 }
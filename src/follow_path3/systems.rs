@@ -1,39 +1,366 @@
-use crate::follow_path3::components::FollowPath3;
+use crate::anchor::{path_space, Hierarchy};
+use crate::follow_path3::components::{FollowPath3, RotationMode};
+use crate::follow_path3::events::{PathFinished, WaypointReached};
+use crate::follow_path3::path::Path3;
+use crate::follow_path3::plugin::FOLLOW_PATH3_TIMESTEP;
+use crate::speed::{braking_speed, ramp};
+use crate::timestep::{delta_seconds, PathTimeScale};
+use crate::traversal::{Advance, OnFinish, PathDirection};
 use bevy::prelude::*;
-use euclid::approxeq::ApproxEq;
+use bevy::time::FixedTimesteps;
+use bevy::utils::HashMap;
+use std::f32::consts::PI;
+
+/// Query for the entities following paths, with their parents
+type Followers<'w, 's, 'a> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'a mut Transform,
+        &'a mut FollowPath3,
+        Option<&'a Parent>,
+    ),
+>;
 
 /// Moves entities along their set path
+///
+/// Systems that move [anchors](FollowPath3::anchor) should run before this one, so the entities
+/// following them are carried along in the same frame.
+#[allow(clippy::too_many_arguments)]
 pub fn advance_paths(
-    mut query: Query<(Entity, &mut Transform, &mut FollowPath3)>,
+    mut queries: ParamSet<(Followers, Hierarchy)>,
+    mut spaces: Local<HashMap<Entity, Mat4>>,
+    paths: Res<Assets<Path3>>,
     mut commands: Commands,
+    mut finished_events: EventWriter<PathFinished>,
+    mut waypoint_events: EventWriter<WaypointReached>,
+    time: Res<Time>,
+    fixed_timesteps: Res<FixedTimesteps>,
+    time_scale: Res<PathTimeScale>,
 ) {
-    for (entity, mut transform, mut follow_path) in query.iter_mut() {
-        let (distance, cur_target) = {
-            let cur_target = follow_path.path.points[follow_path.cur_target];
-            let distance = cur_target.distance(transform.translation);
-            if distance.approx_eq_eps(&0., &follow_path.epsilon) {
-                let cur_target = follow_path.cur_target + 1;
-                if cur_target < follow_path.path.points.len() {
-                    follow_path.cur_target = cur_target;
-                } else if follow_path.path.is_loop {
-                    follow_path.cur_target = 0;
-                } else {
+    let delta = delta_seconds(&time, &fixed_timesteps, FOLLOW_PATH3_TIMESTEP) * time_scale.0;
+    let anchored: Vec<_> = queries
+        .p0()
+        .iter()
+        .filter_map(|(entity, _, follow_path, parent)| {
+            Some((
+                entity,
+                follow_path.anchor?,
+                parent.map(|parent| parent.get()),
+            ))
+        })
+        .collect();
+    spaces.clear();
+    for (entity, anchor, parent) in anchored {
+        if let Some(space) = path_space(anchor, parent, &queries.p1()) {
+            spaces.insert(entity, space);
+        }
+    }
+
+    for (entity, mut transform, mut follow_path, _) in queries.p0().iter_mut() {
+        let space = spaces.get(&entity).copied();
+        // Paused entities are still carried along by their anchor
+        if follow_path.paused && space.is_none() {
+            continue;
+        }
+        let follow_path = &mut *follow_path;
+        let path = match paths.get(&follow_path.path) {
+            Some(path) => path,
+            // The path has not been loaded yet
+            None => continue,
+        };
+        // Keep the progress along paths that changed, like when their file is reloaded
+        let length = path.length();
+        if let Some(previous) = follow_path.path_length.filter(|previous| *previous > 0.) {
+            follow_path.distance *= length / previous;
+        }
+        follow_path.path_length = Some(length);
+        // Stop waiting at waypoints that are gone
+        let waypoints = path.waypoints().len();
+        if follow_path
+            .dwell
+            .is_some_and(|dwell| dwell.waypoint >= waypoints)
+        {
+            follow_path.dwell = None;
+        }
+        let elapsed = if follow_path.paused {
+            0.
+        } else {
+            delta * follow_path.time_scale
+        };
+        let mut delta = elapsed;
+        if let Some(dwell) = &mut follow_path.dwell {
+            dwell.remaining -= delta;
+            if dwell.remaining > 0. {
+                delta = 0.;
+            } else {
+                // Time left over after waiting is spent moving on
+                delta = -dwell.remaining;
+                follow_path.dwell = None;
+            }
+        }
+
+        if delta > 0. {
+            let stop = path.distance_to_stop(
+                follow_path.distance,
+                follow_path.direction,
+                follow_path.cycles_completed,
+            );
+            let speed = path
+                .speed_at(follow_path.distance)
+                .unwrap_or(follow_path.speed);
+            let deceleration = if follow_path.max_deceleration.is_finite() {
+                follow_path.max_deceleration
+            } else {
+                follow_path.max_lateral_acceleration
+            };
+            let cornering = path.cornering_speed(
+                follow_path.distance,
+                follow_path.direction,
+                follow_path.max_lateral_acceleration,
+                deceleration,
+                speed,
+            );
+            let target = speed
+                .min(braking_speed(follow_path.max_deceleration, stop, delta))
+                .min(cornering);
+            follow_path.current_speed = ramp(
+                follow_path.current_speed,
+                target,
+                follow_path.max_acceleration,
+                follow_path.max_deceleration,
+                delta,
+            );
+        }
+
+        // Paused entities neither move nor finish
+        let advance = if follow_path.paused {
+            Advance::default()
+        } else {
+            path.advance(
+                &mut follow_path.distance,
+                follow_path.current_speed * delta,
+                follow_path.direction,
+                &mut follow_path.cycles_completed,
+            )
+        };
+        if advance.turned {
+            follow_path.reverse();
+            follow_path.current_speed = 0.;
+        }
+        if advance.dwell.is_some() {
+            follow_path.dwell = advance.dwell;
+            follow_path.current_speed = 0.;
+        }
+
+        for reached in advance.reached {
+            waypoint_events.send(WaypointReached {
+                entity,
+                waypoint: reached.waypoint,
+                point: reached.point,
+                completed_loop: reached.completed_loop,
+                tag: path.waypoint_action(reached.waypoint).tag.clone(),
+            });
+        }
+
+        if advance.finished {
+            finished_events.send(PathFinished {
+                entity,
+                path: follow_path.path.clone(),
+            });
+            match follow_path.on_finish {
+                OnFinish::Remove => {
                     commands.entity(entity).remove::<FollowPath3>();
-                    return;
                 }
-                let cur_target = follow_path.path.points[follow_path.cur_target];
-                (cur_target.distance(transform.translation), cur_target)
-            } else {
-                (distance, cur_target)
+                OnFinish::Idle => {}
+                OnFinish::Despawn => {
+                    commands.entity(entity).despawn_recursive();
+                }
+                OnFinish::Reverse => {
+                    follow_path.reverse();
+                    follow_path.current_speed = 0.;
+                    follow_path.cycles_completed = 0;
+                }
             }
+        }
+
+        // The entity's transform within the space of its path
+        let mut path_transform = match space {
+            Some(space) => Transform::from_matrix(space.inverse() * transform.compute_matrix()),
+            None => *transform,
         };
-        let direction = (cur_target - transform.translation).normalize();
+        let distance = follow_path.distance;
+        follow_path.shift(elapsed);
+        if let Some(position) =
+            path.offset_at(distance, follow_path.current_offset, follow_path.up_axis)
+        {
+            path_transform.translation = position;
+        }
 
-        transform.look_at(cur_target, follow_path.up_axis);
+        let facing = follow_path
+            .dwell
+            .and_then(|dwell| path.waypoint_action(dwell.waypoint).facing);
+        let orientation = path
+            .orientation_at(distance, follow_path.up_axis)
+            .map(|orientation| match follow_path.direction {
+                PathDirection::Forward => orientation,
+                PathDirection::Backward => orientation * Quat::from_rotation_y(PI),
+            });
+        let rotation = match follow_path.rotation_mode {
+            RotationMode::None => None,
+            _ if facing.is_some() => facing,
+            RotationMode::FaceDirection => orientation,
+            RotationMode::LookAhead(ahead) => {
+                let up =
+                    orientation.map_or(follow_path.up_axis, |orientation| orientation * Vec3::Y);
+                look_ahead_direction(path, follow_path, distance, ahead)
+                    .map(|direction| Transform::identity().looking_at(direction, up).rotation)
+            }
+            RotationMode::Fixed(rotation) => Some(rotation),
+        };
+        let along_path = facing.is_none()
+            && matches!(
+                follow_path.rotation_mode,
+                RotationMode::FaceDirection | RotationMode::LookAhead(_)
+            );
+        if let Some(mut rotation) = rotation {
+            if along_path {
+                let right = rotation * Vec3::X;
+                follow_path.bank(path.bend_at(distance).dot(right), elapsed);
+                rotation *= Quat::from_rotation_z(-follow_path.bank_angle);
+            }
+            path_transform.rotation = rotation;
+        }
 
-        let speed = follow_path.speed.min(distance);
-        transform.translation.x += direction.x * speed;
-        transform.translation.y += direction.y * speed;
-        transform.translation.z += direction.z * speed;
+        if let Some(space) = space {
+            path_transform = Transform::from_matrix(space * path_transform.compute_matrix());
+        }
+        transform.translation = path_transform.translation;
+        transform.rotation = path_transform.rotation;
+    }
+}
+
+/// Direction the entity travels in at *distance*
+fn travel_direction(path: &Path3, follow_path: &FollowPath3, distance: f32) -> Option<Vec3> {
+    Some(path.direction_at(distance)? * follow_path.direction.sign())
+}
+
+/// Direction towards the point *ahead* units further along the path than *distance*
+///
+/// Falls back to the [travel direction](travel_direction) where there is nothing ahead, like at the end of the path.
+fn look_ahead_direction(
+    path: &Path3,
+    follow_path: &FollowPath3,
+    distance: f32,
+    ahead: f32,
+) -> Option<Vec3> {
+    let target = path.sample_at_distance(distance + ahead * follow_path.direction.sign())?;
+    (target - path.sample_at_distance(distance)?)
+        .try_normalize()
+        .or_else(|| travel_direction(path, follow_path, distance))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::follow_path3::components::Banking;
+    use crate::follow_path3::plugin::FollowPath3Plugin;
+    use crate::traversal::PathMode;
+    use std::f32::consts::TAU;
+    use std::time::{Duration, Instant};
+
+    /// An app that moves followers along their paths, with its time starting at the returned instant
+    fn app() -> (App, Instant) {
+        let mut app = App::new();
+        app.add_plugin(bevy::core::CorePlugin)
+            .add_plugin(bevy::asset::AssetPlugin)
+            .init_resource::<FixedTimesteps>()
+            .add_plugin(FollowPath3Plugin::default());
+        let start = Instant::now();
+        let mut time = Time::default();
+        time.update_with_instant(start);
+        app.insert_resource(time);
+        (app, start)
+    }
+
+    /// Run a frame that took *seconds*
+    fn step(app: &mut App, now: &mut Instant, seconds: f32) {
+        *now += Duration::from_secs_f32(seconds);
+        app.world.resource_mut::<Time>().update_with_instant(*now);
+        app.update();
+    }
+
+    /// A level circle around the origin with a radius of 10, bending to the left or the right
+    fn circle(left: bool) -> Path3 {
+        let turn = if left { -TAU } else { TAU };
+        let points = (0..64)
+            .map(|i| {
+                let angle = i as f32 / 64. * turn;
+                Vec3::new(angle.cos() * 10., 0., angle.sin() * 10.)
+            })
+            .collect();
+        Path3::new(points, PathMode::Loop)
+    }
+
+    /// Follow *path* at 5 units per second for *frames* steps of a tenth of a second
+    ///
+    /// Returns the bank angle, and how far the entity's up axis leans towards the origin.
+    fn bank(path: Path3, banking: Banking, frames: usize) -> (f32, f32) {
+        let (mut app, mut now) = app();
+        let path = app.world.resource_mut::<Assets<Path3>>().add(path);
+        let mut follow_path = FollowPath3::new(path, 5.);
+        follow_path.banking = Some(banking);
+        let entity = app
+            .world
+            .spawn()
+            .insert(Transform::default())
+            .insert(follow_path)
+            .id();
+        for _ in 0..frames {
+            step(&mut app, &mut now, 0.1);
+        }
+        let transform = app.world.get::<Transform>(entity).unwrap();
+        let inward = -transform.translation.normalize();
+        let lean = (transform.rotation * Vec3::Y).dot(inward);
+        (
+            app.world.get::<FollowPath3>(entity).unwrap().bank_angle,
+            lean,
+        )
+    }
+
+    #[test]
+    fn followers_bank_into_bends() {
+        let banking = Banking {
+            strength: 1.,
+            max_angle: 1.,
+            smoothing: 4.,
+        };
+        // A curvature of 0.1 at a speed of 5 asks for half a radian, leaning left on left bends
+        let (angle, lean) = bank(circle(true), banking, 60);
+        assert!((angle + 0.5).abs() < 0.01, "{}", angle);
+        assert!((lean - 0.5_f32.sin()).abs() < 0.01, "{}", lean);
+        let (angle, lean) = bank(circle(false), banking, 60);
+        assert!((angle - 0.5).abs() < 0.01, "{}", angle);
+        assert!((lean - 0.5_f32.sin()).abs() < 0.01, "{}", lean);
+
+        // After one step, the bank angle has only closed part of the gap
+        let (angle, _) = bank(circle(false), banking, 1);
+        let expected = 0.5 * (1. - (-0.4_f32).exp());
+        assert!((angle - expected).abs() < 0.01, "{}", angle);
+    }
+
+    #[test]
+    fn bank_angles_are_limited() {
+        let banking = Banking {
+            strength: 10.,
+            max_angle: 0.3,
+            smoothing: f32::INFINITY,
+        };
+        let (angle, lean) = bank(circle(false), banking, 1);
+        assert_eq!(angle, 0.3);
+        assert!((lean - 0.3_f32.sin()).abs() < 0.01, "{}", lean);
+        let (angle, _) = bank(circle(true), banking, 1);
+        assert_eq!(angle, -0.3);
     }
 }
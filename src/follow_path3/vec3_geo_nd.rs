@@ -10,7 +10,7 @@ pub(crate) struct Vec3Geo(pub(crate) Vec3);
 
 impl Clone for Vec3Geo {
     fn clone(&self) -> Self {
-        Self(self.0)
+        *self
     }
 }
 
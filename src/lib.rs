@@ -1,5 +1,12 @@
+mod anchor;
+pub mod arc_length;
 pub mod follow_path2;
 pub mod follow_path3;
+#[cfg(feature = "serde")]
+pub mod path_file;
+pub mod speed;
+pub mod timestep;
+pub mod traversal;
 
 #[cfg(test)]
 mod tests {}
@@ -0,0 +1,254 @@
+use crate::follow_path2::path::{Path2, PathBuilder as PathBuilder2};
+use crate::follow_path3::path::{Path3, PathBuilder as PathBuilder3};
+use crate::traversal::PathMode;
+use bevy::asset::{AssetLoader, BoxedFuture, Error, LoadContext, LoadedAsset};
+use serde::de::DeserializeOwned;
+
+/// Contents of a path file for a [Path2], as loaded by the [Path2FileLoader]
+#[derive(serde::Serialize, serde::Deserialize)]
+pub enum Path2File {
+    /// A [Path2] given by its points
+    Path2(Path2),
+    /// A [Path2] built from the segments of a [PathBuilder](PathBuilder2)
+    Builder2 {
+        /// How the built path is traversed
+        #[serde(default)]
+        mode: PathMode,
+        /// The builder holding the segments
+        builder: PathBuilder2,
+    },
+}
+
+/// Contents of a path file for a [Path3], as loaded by the [Path3FileLoader]
+#[derive(serde::Serialize, serde::Deserialize)]
+pub enum Path3File {
+    /// A [Path3] given by its points
+    Path3(Box<Path3>),
+    /// A [Path3] built from the segments of a [PathBuilder](PathBuilder3)
+    Builder3 {
+        /// How the built path is traversed
+        #[serde(default)]
+        mode: PathMode,
+        /// The builder holding the segments
+        builder: PathBuilder3,
+    },
+}
+
+/// Loads [Path2] assets from `.path2.ron` and `.path2.json` files holding a [Path2File]
+#[derive(Default)]
+pub struct Path2FileLoader;
+
+impl AssetLoader for Path2FileLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let path = match read(bytes, load_context)? {
+                Path2File::Path2(path) => path,
+                Path2File::Builder2 { mode, builder } => builder.build_path_with_mode(mode),
+            };
+            load_context.set_default_asset(LoadedAsset::new(path));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["path2.ron", "path2.json"]
+    }
+}
+
+/// Loads [Path3] assets from `.path3.ron` and `.path3.json` files holding a [Path3File]
+#[derive(Default)]
+pub struct Path3FileLoader;
+
+impl AssetLoader for Path3FileLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let path = match read(bytes, load_context)? {
+                Path3File::Path3(path) => *path,
+                Path3File::Builder3 { mode, builder } => builder.build_path_with_mode(mode),
+            };
+            load_context.set_default_asset(LoadedAsset::new(path));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["path3.ron", "path3.json"]
+    }
+}
+
+/// Read a path file as JSON if its extension says so, and as RON otherwise
+fn read<T: DeserializeOwned>(bytes: &[u8], load_context: &LoadContext) -> Result<T, Error> {
+    let json = load_context
+        .path()
+        .extension()
+        .is_some_and(|extension| extension == "json");
+    if json {
+        Ok(serde_json::from_slice(bytes)?)
+    } else {
+        Ok(ron::de::from_bytes(bytes)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::follow_path2::path::WaypointAction;
+    use crate::follow_path2::plugin::FollowPath2Plugin;
+    use crate::follow_path3::path::Twist;
+    use crate::follow_path3::plugin::FollowPath3Plugin;
+    use bevy::asset::{AssetServerSettings, LoadState};
+    use bevy::prelude::*;
+    use bevy::time::FixedTimesteps;
+
+    const BUILDER2: &str = r#"Builder2(
+        mode: Loop,
+        builder: (
+            segments: [
+                Point((0.0, 0.0)),
+                QuadraticBezierCurve(to: (10.0, 0.0), ctrl: (5.0, 5.0), straightness: 0.1),
+            ],
+            actions: [(), (wait: 1.0, tag: Some("end"))],
+        ),
+    )"#;
+
+    const PATH3: &str = r#"{"Path3": {
+        "points": [[0.0, 0.0, 0.0], [0.0, 0.0, -10.0], [10.0, 0.0, -10.0]],
+        "twists": [[1, {"Roll": 0.5}]]
+    }}"#;
+
+    #[test]
+    fn paths_read_back_the_same() {
+        let path = match ron::from_str(BUILDER2).unwrap() {
+            Path2File::Builder2 { mode, builder } => builder.build_path_with_mode(mode),
+            Path2File::Path2(_) => panic!("Expected a builder"),
+        };
+        assert_eq!(path.mode, PathMode::Loop);
+        assert!(path.points().len() > 2);
+        assert_eq!(path.waypoint_action(1).tag.as_deref(), Some("end"));
+
+        let written = ron::to_string(&Path2File::Path2(path.clone())).unwrap();
+        let read = match ron::from_str(&written).unwrap() {
+            Path2File::Path2(path) => path,
+            Path2File::Builder2 { .. } => panic!("Expected a path"),
+        };
+        assert_eq!(read.points(), path.points());
+        assert_eq!(read.waypoints(), path.waypoints());
+        assert_eq!(read.mode, path.mode);
+        for waypoint in 0..path.waypoints().len() {
+            assert_eq!(
+                read.waypoint_action(waypoint),
+                path.waypoint_action(waypoint)
+            );
+        }
+    }
+
+    #[test]
+    fn builders_read_back_the_same() {
+        let mut builder = PathBuilder2::new(Vec2::ZERO);
+        builder.add_cubic_bezier_curve(Vec2::X, Vec2::Y, Vec2::ONE, 0.1);
+        builder.set_action(WaypointAction {
+            wait: 2.,
+            ..default()
+        });
+        builder.set_speed(3.);
+        let file = Path2File::Builder2 {
+            mode: PathMode::PingPong,
+            builder: builder.clone(),
+        };
+        let written = serde_json::to_string(&file).unwrap();
+        match serde_json::from_str(&written).unwrap() {
+            Path2File::Builder2 {
+                mode,
+                builder: read,
+            } => {
+                assert_eq!(mode, PathMode::PingPong);
+                assert_eq!(read.segments(), builder.segments());
+                let (read, built) = (read.build_path(), builder.build_path());
+                assert_eq!(read.waypoint_action(1), built.waypoint_action(1));
+                assert_eq!(read.speed_keys(), built.speed_keys());
+            }
+            Path2File::Path2(_) => panic!("Expected a builder"),
+        }
+    }
+
+    #[test]
+    fn invalid_paths_are_rejected() {
+        let error = |ron: &str| ron::from_str::<Path2File>(ron).err().unwrap().to_string();
+        assert!(error("Path2((points: [(0.0, 0.0)], waypoints: Some([1])))")
+            .contains("Waypoint index out of bounds"));
+        assert!(error("Path2((points: [(0.0, 0.0)], actions: [(), ()]))")
+            .contains("More actions than waypoints"));
+        assert!(error(
+            "Builder2(builder: (segments: [QuadraticBezierCurve(to: (1.0, 0.0), ctrl: (0.0, 1.0), straightness: 0.1)]))"
+        )
+        .contains("Path has to start with a Point"));
+        let error = ron::from_str::<Path3File>(
+            "Path3((points: [(0.0, 0.0, 0.0)], twists: [(1, Roll(1.0))]))",
+        )
+        .err()
+        .unwrap()
+        .to_string();
+        assert!(error.contains("Twist index out of bounds"), "{}", error);
+    }
+
+    #[test]
+    fn files_for_the_other_dimension_are_rejected() {
+        assert!(ron::from_str::<Path2File>("Path3((points: [(0.0, 0.0, 0.0)]))").is_err());
+        assert!(serde_json::from_str::<Path2File>(PATH3).is_err());
+    }
+
+    #[test]
+    fn path_files_are_loaded_as_assets() {
+        let folder = std::env::temp_dir().join(format!("path_files_{}", std::process::id()));
+        std::fs::create_dir_all(&folder).unwrap();
+        std::fs::write(folder.join("route.path2.ron"), BUILDER2).unwrap();
+        std::fs::write(folder.join("route.path3.json"), PATH3).unwrap();
+        std::fs::write(folder.join("wrong.path2.json"), PATH3).unwrap();
+
+        let mut app = App::new();
+        app.insert_resource(AssetServerSettings {
+            asset_folder: folder.to_string_lossy().into_owned(),
+            ..default()
+        })
+        .add_plugin(bevy::core::CorePlugin)
+        .add_plugin(bevy::asset::AssetPlugin)
+        .init_resource::<FixedTimesteps>()
+        .insert_resource(Time::default())
+        .add_plugin(FollowPath2Plugin::default())
+        .add_plugin(FollowPath3Plugin::default());
+        let server = app.world.resource::<AssetServer>().clone();
+        let route2: Handle<Path2> = server.load("route.path2.ron");
+        let route3: Handle<Path3> = server.load("route.path3.json");
+        let wrong: Handle<Path2> = server.load("wrong.path2.json");
+        let handles = [route2.id, route3.id, wrong.id];
+        for _ in 0..200 {
+            app.update();
+            if handles.iter().all(|handle| {
+                matches!(
+                    server.get_load_state(*handle),
+                    LoadState::Loaded | LoadState::Failed
+                )
+            }) {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        std::fs::remove_dir_all(&folder).unwrap();
+
+        assert_eq!(server.get_load_state(&route2), LoadState::Loaded);
+        assert_eq!(server.get_load_state(&route3), LoadState::Loaded);
+        assert_eq!(server.get_load_state(&wrong), LoadState::Failed);
+        let route2 = app.world.resource::<Assets<Path2>>().get(&route2).unwrap();
+        assert_eq!(route2.mode, PathMode::Loop);
+        let route3 = app.world.resource::<Assets<Path3>>().get(&route3).unwrap();
+        assert_eq!(route3.twists(), &[(1, Twist::Roll(0.5))]);
+    }
+}
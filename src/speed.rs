@@ -0,0 +1,229 @@
+use crate::arc_length::ArcLengths;
+
+/// Where along a path a [SpeedKey] lies
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum KeyPosition {
+    /// Distance along the path from its first point
+    Distance(f32),
+    /// Index of a waypoint within the path's waypoints
+    Waypoint(usize),
+}
+
+/// Speed a follower should move at when it passes a position on a path
+///
+/// Speeds between keys are interpolated linearly.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SpeedKey {
+    /// Where the key lies on the path
+    pub at: KeyPosition,
+    /// Speed at the key, in units per second
+    pub speed: f32,
+}
+
+/// Resolve the positions of *keys* into distances, sorted along the path
+///
+/// Keys referring to waypoints that do not exist are skipped.
+pub(crate) fn resolve_keys(
+    keys: &[SpeedKey],
+    lengths: &ArcLengths,
+    waypoints: &[usize],
+) -> Vec<(f32, f32)> {
+    let mut resolved: Vec<(f32, f32)> = keys
+        .iter()
+        .filter_map(|key| {
+            let at = match key.at {
+                KeyPosition::Distance(distance) => distance,
+                KeyPosition::Waypoint(waypoint) => lengths.distance_to(*waypoints.get(waypoint)?),
+            };
+            Some((at, key.speed))
+        })
+        .collect();
+    resolved.sort_by(|a, b| a.0.total_cmp(&b.0));
+    resolved
+}
+
+/// Interpolate the speed at *distance* between *keys* resolved by [resolve_keys]
+///
+/// Keys wrap around closed paths of *length*. Returns [None] if there are no keys.
+pub(crate) fn interpolate_keys(
+    keys: &[(f32, f32)],
+    distance: f32,
+    length: f32,
+    closed: bool,
+) -> Option<f32> {
+    let (first, last) = (*keys.first()?, *keys.last()?);
+    let next = keys.partition_point(|(at, _)| *at <= distance);
+    let (before, after) = match (keys.get(next.wrapping_sub(1)), keys.get(next)) {
+        (Some(before), Some(after)) => (*before, *after),
+        (None, _) if closed => ((last.0 - length, last.1), first),
+        (_, None) if closed => (last, (first.0 + length, first.1)),
+        (None, _) => return Some(first.1),
+        (_, None) => return Some(last.1),
+    };
+    let span = after.0 - before.0;
+    if span > 0. {
+        Some(before.1 + (after.1 - before.1) * ((distance - before.0) / span))
+    } else {
+        Some(after.1)
+    }
+}
+
+/// Highest speed at *distance* from which a follower can slow down in time for every bend ahead
+///
+/// *curvatures* holds the curvature at each point of a closed path; the ends of open paths are
+/// treated as straight. The speed through a bend is limited so the follower's lateral
+/// acceleration stays below *max_lateral_acceleration*. Bends are looked ahead for as far as it
+/// takes to brake from *max_speed* at *deceleration*.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn cornering_speed(
+    lengths: &ArcLengths,
+    curvatures: &[f32],
+    closed: bool,
+    distance: f32,
+    forward: bool,
+    max_lateral_acceleration: f32,
+    deceleration: f32,
+    max_speed: f32,
+) -> f32 {
+    let location = match lengths.locate(distance, closed) {
+        Some(location) if max_lateral_acceleration.is_finite() => location,
+        _ => return f32::INFINITY,
+    };
+    let count = curvatures.len();
+    let horizon = max_speed * max_speed / (2. * deceleration);
+    let corner_speed = |index: usize, ahead: f32| {
+        let open_end = !closed && (index == 0 || index + 1 == count);
+        if open_end || curvatures[index] <= 0. {
+            return f32::INFINITY;
+        }
+        (max_lateral_acceleration / curvatures[index] + 2. * deceleration * ahead).sqrt()
+    };
+
+    let segment = lengths.segment_length(location.start);
+    let (mut index, mut ahead) = if forward {
+        (location.end, (1. - location.fraction) * segment)
+    } else {
+        (location.start, location.fraction * segment)
+    };
+    let mut speed = f32::INFINITY;
+    if forward && location.fraction == 0. {
+        speed = corner_speed(location.start, 0.);
+    }
+    for _ in 0..count {
+        speed = speed.min(corner_speed(index, ahead));
+        if ahead > horizon {
+            break;
+        }
+        let next = if forward {
+            match index + 1 {
+                next if next < count => next,
+                _ if closed => 0,
+                _ => break,
+            }
+        } else {
+            match index.checked_sub(1) {
+                Some(previous) => previous,
+                None if closed => count - 1,
+                None => break,
+            }
+        };
+        ahead += lengths.segment_length(if forward { index } else { next });
+        index = next;
+    }
+    speed
+}
+
+/// Highest speed from which a follower moving in steps of *delta* seconds can still stop within
+/// *distance*
+///
+/// The follower covers a whole step at each speed before slowing down further, so stopping takes
+/// `speed * delta / 2` more than the `speed² / (2 * max_deceleration)` of a smooth stop.
+pub(crate) fn braking_speed(max_deceleration: f32, distance: f32, delta: f32) -> f32 {
+    if max_deceleration.is_finite() {
+        let half_step = max_deceleration * delta / 2.;
+        (half_step * half_step + 2. * max_deceleration * distance.max(0.)).sqrt() - half_step
+    } else {
+        f32::INFINITY
+    }
+}
+
+/// Move *current* towards *target*, changing it by no more than the given limits over *delta* seconds
+pub(crate) fn ramp(
+    current: f32,
+    target: f32,
+    max_acceleration: f32,
+    max_deceleration: f32,
+    delta: f32,
+) -> f32 {
+    if target > current {
+        (current + max_acceleration * delta).min(target)
+    } else {
+        (current - max_deceleration * delta).max(target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn braking_speeds_slow_down_by_the_deceleration_each_step() {
+        for stop in [1., 4.5, 20.] {
+            let speed = braking_speed(4., stop, 0.1);
+            let next = braking_speed(4., stop - speed * 0.1, 0.1);
+            assert!((speed - next - 0.4).abs() < 1e-4, "{} {}", speed, next);
+        }
+        // Within a step of the stop, the braking speed reaches it in that step
+        let speed = braking_speed(4., 0.03, 0.1);
+        assert!(speed <= 0.4 && speed * 0.1 >= 0.03, "{}", speed);
+        assert_eq!(braking_speed(4., 0., 0.1), 0.);
+        assert_eq!(braking_speed(4., -1., 0.1), 0.);
+        assert_eq!(braking_speed(f32::INFINITY, 1., 0.1), f32::INFINITY);
+    }
+
+    #[test]
+    fn ramps_change_by_the_limits_without_passing_the_target() {
+        assert_eq!(ramp(0., 6., 4., 2., 0.5), 2.);
+        assert_eq!(ramp(5., 6., 4., 2., 0.5), 6.);
+        assert_eq!(ramp(6., 0., 4., 2., 0.5), 5.);
+        assert_eq!(ramp(6., 5.5, 4., 2., 0.5), 5.5);
+        assert_eq!(ramp(0., 6., f32::INFINITY, 2., 0.5), 6.);
+        assert_eq!(ramp(6., 0., 4., f32::INFINITY, 0.5), 0.);
+    }
+
+    /// Points at 0, 10, 20 and 30 along a line, with a bend at the third
+    fn bend(closed: bool, distance: f32, forward: bool, max_speed: f32) -> f32 {
+        let lengths = ArcLengths::new(&[0., 10., 20., 30.], |a: &f32, b: &f32| (a - b).abs());
+        let curvatures = [1., 0., 0.5, 1.];
+        cornering_speed(
+            &lengths,
+            &curvatures,
+            closed,
+            distance,
+            forward,
+            2.,
+            1.,
+            max_speed,
+        )
+    }
+
+    #[test]
+    fn cornering_speeds_leave_room_to_brake_for_bends_ahead() {
+        // Taking the bend at 2 gives a lateral acceleration of 2
+        assert_eq!(bend(false, 20., true, 100.), 2.);
+        assert_eq!(bend(false, 12., true, 100.), 20_f32.sqrt());
+        assert_eq!(bend(false, 25., false, 100.), 14_f32.sqrt());
+        // Bends behind do not matter, and neither do the ends of open paths
+        assert_eq!(bend(false, 25., true, 100.), f32::INFINITY);
+        assert_eq!(bend(false, 5., false, 100.), f32::INFINITY);
+        // Closed paths bend at their ends as well
+        assert_eq!(bend(true, 25., true, 100.), 12_f32.sqrt());
+        // Bends further away than it takes to brake from the highest speed are left out
+        assert_eq!(bend(false, 0., true, 4.), f32::INFINITY);
+        let lengths = ArcLengths::new(&[0., 10.], |a: &f32, b: &f32| (a - b).abs());
+        let unlimited = cornering_speed(&lengths, &[1., 1.], true, 0., true, f32::INFINITY, 1., 5.);
+        assert_eq!(unlimited, f32::INFINITY);
+    }
+}
@@ -0,0 +1,29 @@
+use bevy::prelude::*;
+use bevy::time::FixedTimesteps;
+
+/// Clock that drives the path following systems
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum PathTimestep {
+    /// Advance once per frame, scaled by the frame's delta time
+    #[default]
+    Frame,
+    /// Advance at a fixed rate, using the given step in seconds
+    Fixed(f64),
+}
+
+/// Resource that scales the time of every path follower, e.g. for slow motion or pausing
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PathTimeScale(pub f32);
+
+impl Default for PathTimeScale {
+    fn default() -> Self {
+        Self(1.)
+    }
+}
+
+/// Returns the seconds to advance by, using the fixed step registered under *label* if there is one
+pub(crate) fn delta_seconds(time: &Time, fixed_timesteps: &FixedTimesteps, label: &str) -> f32 {
+    fixed_timesteps
+        .get(label)
+        .map_or_else(|| time.delta_seconds(), |state| state.step() as f32)
+}
@@ -0,0 +1,562 @@
+use crate::arc_length::ArcLengths;
+
+/// How a follower traverses a path
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PathMode {
+    /// Follow the path from the first to the last point and finish there
+    #[default]
+    Once,
+    /// Circle back to the first point after the last one, forever
+    Loop,
+    /// Walk back along the points after reaching either end, forever
+    PingPong,
+    /// Circle back to the first point after the last one, finishing after the given number of cycles
+    Repeat(u32),
+}
+
+impl PathMode {
+    /// Whether the last point of the path connects back to the first one
+    pub fn is_closed(&self) -> bool {
+        matches!(self, Self::Loop | Self::Repeat(_))
+    }
+
+    /// Number of cycles until the path is finished, or [None] if it never finishes
+    pub fn cycles(&self) -> Option<u32> {
+        match self {
+            Self::Once => Some(1),
+            Self::Loop | Self::PingPong => None,
+            Self::Repeat(cycles) => Some(*cycles),
+        }
+    }
+}
+
+/// Direction in which a follower moves along a path
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PathDirection {
+    /// Move from the first point towards the last one
+    #[default]
+    Forward,
+    /// Move from the last point towards the first one
+    Backward,
+}
+
+impl PathDirection {
+    /// The opposite direction
+    pub fn reversed(&self) -> Self {
+        match self {
+            Self::Forward => Self::Backward,
+            Self::Backward => Self::Forward,
+        }
+    }
+
+    /// 1 when moving forward and -1 when moving backward
+    pub fn sign(&self) -> f32 {
+        match self {
+            Self::Forward => 1.,
+            Self::Backward => -1.,
+        }
+    }
+}
+
+/// What happens to a follower when it reaches the end of a path that does not loop
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum OnFinish {
+    /// Remove the follow component from the entity
+    #[default]
+    Remove,
+    /// Keep the follow component and stay at the end of the path
+    Idle,
+    /// Despawn the entity, including its children
+    Despawn,
+    /// Turn around and follow the path back to the other end
+    Reverse,
+}
+
+/// A follower waiting at a waypoint before it continues along the path
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Dwell {
+    /// Index of the waypoint within the path's waypoints
+    pub waypoint: usize,
+    /// Seconds left to wait
+    pub remaining: f32,
+}
+
+/// A waypoint passed while moving a follower along a path
+pub(crate) struct Reached {
+    /// Index of the waypoint within the path's waypoints
+    pub(crate) waypoint: usize,
+    /// Index of the waypoint's point within the path's points
+    pub(crate) point: usize,
+    /// Whether reaching the waypoint completed a loop around the path
+    pub(crate) completed_loop: bool,
+}
+
+/// Outcome of moving a follower along a path
+#[derive(Default)]
+pub(crate) struct Advance {
+    /// Waypoints passed, in order
+    pub(crate) reached: Vec<Reached>,
+    /// Set when the follower stopped at a waypoint it has to wait at
+    pub(crate) dwell: Option<Dwell>,
+    /// Whether the follower bounced off the ends an odd number of times and now moves the other way
+    pub(crate) turned: bool,
+    /// Whether the follower finished the path
+    pub(crate) finished: bool,
+}
+
+/// Move *distance* by *travel* along a path in the given direction, following *mode*
+///
+/// *cycles* holds the number of completed cycles and is updated as the follower completes more.
+/// Movement stops early at the first passed waypoint for which *wait_at* returns a positive time.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn advance(
+    lengths: &ArcLengths,
+    waypoints: &[usize],
+    wait_at: impl Fn(usize) -> f32,
+    mode: PathMode,
+    distance: &mut f32,
+    travel: f32,
+    forward: bool,
+    cycles: &mut u32,
+) -> Advance {
+    let closed = mode.is_closed();
+    let mut reached = Vec::new();
+    let mut dwell = None;
+    let mut advance = move_along(
+        distance,
+        travel,
+        forward,
+        cycles,
+        lengths.length(closed),
+        mode,
+        |from, to| {
+            for (waypoint, at) in lengths.passed(waypoints, from, to, closed) {
+                let point = waypoints[waypoint];
+                // Ping-pong paths complete a cycle when arriving back at the start
+                let completed_loop =
+                    point == 0 && (closed || (mode == PathMode::PingPong && to < from));
+                reached.push(Reached {
+                    waypoint,
+                    point,
+                    completed_loop,
+                });
+                let remaining = wait_at(waypoint);
+                if remaining > 0. {
+                    dwell = Some(Dwell {
+                        waypoint,
+                        remaining,
+                    });
+                    return Some(at);
+                }
+            }
+            None
+        },
+    );
+
+    // Finishing the path takes over from waiting at its last waypoint
+    if let (Some(dwell), false) = (dwell, advance.finished) {
+        *distance = lengths.normalize(lengths.distance_to(waypoints[dwell.waypoint]), closed);
+        advance.dwell = Some(dwell);
+    }
+    advance.reached = reached;
+    advance
+}
+
+/// Distance a follower at *distance* can travel in the given direction before it has to stop
+///
+/// Followers stop where they finish the path, where they turn around on ping-pong paths and at
+/// the *waiting* positions within *waypoints*, which are sorted along the path.
+pub(crate) fn distance_to_stop(
+    lengths: &ArcLengths,
+    waypoints: &[usize],
+    waiting: &[usize],
+    mode: PathMode,
+    distance: f32,
+    forward: bool,
+    cycles: u32,
+) -> f32 {
+    let closed = mode.is_closed();
+    let length = lengths.length(closed);
+    let remaining = mode.cycles().map(|total| total.saturating_sub(cycles));
+
+    let mut stop = match (mode, remaining) {
+        (PathMode::Loop | PathMode::Repeat(_), _) if length <= 0. || remaining == Some(0) => 0.,
+        (PathMode::Loop, _) => f32::INFINITY,
+        (PathMode::Repeat(_), Some(remaining)) => {
+            let remaining = remaining as f32;
+            if forward {
+                ((distance / length).floor() + remaining) * length - distance
+            } else {
+                distance - ((distance / length).ceil() - remaining) * length
+            }
+        }
+        _ if forward => length - distance,
+        _ => distance,
+    };
+
+    // Only the first waiting waypoint ahead matters, wrapping around to the start on closed paths
+    let at = |position: &usize| lengths.distance_to(waypoints[*position]);
+    let wraps = closed && length > 0.;
+    let ahead = if forward {
+        let next = waiting.partition_point(|position| at(position) <= distance);
+        match waiting.get(next) {
+            Some(position) => Some(at(position) - distance),
+            // The waypoint the follower waits at now lies a full lap ahead
+            None if wraps => waiting
+                .first()
+                .map(|position| at(position) + length - distance),
+            None => None,
+        }
+    } else {
+        let next = waiting.partition_point(|position| at(position) < distance);
+        match next.checked_sub(1) {
+            Some(next) => Some(distance - at(&waiting[next])),
+            None if wraps => waiting
+                .last()
+                .map(|position| distance - at(position) + length),
+            None => None,
+        }
+    };
+    if let Some(ahead) = ahead {
+        stop = stop.min(ahead);
+    }
+    stop.max(0.)
+}
+
+/// Move *distance* by *travel* along a path of *length* in the given direction, following *mode*
+///
+/// *stop* is called with the start and end of every stretch travelled and may return a distance
+/// within it at which to stop.
+fn move_along(
+    distance: &mut f32,
+    travel: f32,
+    forward: bool,
+    cycles: &mut u32,
+    length: f32,
+    mode: PathMode,
+    mut stop: impl FnMut(f32, f32) -> Option<f32>,
+) -> Advance {
+    let mut advance = Advance::default();
+    let from = *distance;
+    let travel = if forward { travel } else { -travel };
+    let remaining = mode.cycles().map(|total| total.saturating_sub(*cycles));
+
+    match mode {
+        PathMode::Loop | PathMode::Repeat(_) if length > 0. && remaining != Some(0) => {
+            let mut to = from + travel;
+            if let Some(remaining) = remaining {
+                // The distance at which the last remaining cycle completes
+                let remaining = remaining as f32;
+                if forward {
+                    to = to.min(((from / length).floor() + remaining) * length);
+                } else {
+                    to = to.max(((from / length).ceil() - remaining) * length);
+                }
+            }
+            let to = stop(from, to).unwrap_or(to);
+            let laps = if to > from {
+                (to / length).floor() - (from / length).floor()
+            } else {
+                (from / length).ceil() - (to / length).ceil()
+            };
+            *cycles += laps as u32;
+
+            if remaining.is_some_and(|remaining| laps as u32 >= remaining) {
+                advance.finished = true;
+                *distance = if forward { length } else { 0. };
+            } else {
+                *distance = to.rem_euclid(length);
+            }
+        }
+        PathMode::PingPong if length > 0. => {
+            let mut from = from;
+            let mut travel = travel;
+            loop {
+                let to = from + travel;
+                if (to > 0. && to < length) || travel == 0. {
+                    *distance = stop(from, to).unwrap_or(to);
+                    break;
+                }
+                // Bounce off the end and carry the rest of the travel back along the path
+                let end = if travel > 0. { length } else { 0. };
+                match stop(from, end) {
+                    Some(at) if at != end => {
+                        *distance = at;
+                        break;
+                    }
+                    stopped => {
+                        if end == 0. {
+                            *cycles += 1;
+                        }
+                        advance.turned = !advance.turned;
+                        if stopped.is_some() {
+                            *distance = end;
+                            break;
+                        }
+                    }
+                }
+                travel = -(to - end);
+                from = end;
+            }
+        }
+        _ => {
+            // Once, or a path that cannot be traversed any further
+            let to = (from + travel).clamp(0., length);
+            let to = stop(from, to).unwrap_or(to);
+            // Followers already standing at the end finish as well, but only while cycles are left
+            let at_end = if forward { to >= length } else { to <= 0. };
+            if let Some(remaining) = remaining.filter(|remaining| *remaining > 0 && at_end) {
+                advance.finished = true;
+                *cycles += remaining;
+            }
+            *distance = to;
+        }
+    }
+    advance
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WAYPOINTS: [usize; 3] = [0, 1, 2];
+
+    /// Points at 0, 10 and 20 along a line, which is 20 long open and 40 long closed
+    fn lengths() -> ArcLengths {
+        ArcLengths::new(&[0., 10., 20.], |a: &f32, b: &f32| (a - b).abs())
+    }
+
+    /// Move along the points, waiting *waits* seconds at each waypoint
+    fn run(
+        mode: PathMode,
+        waits: [f32; 3],
+        distance: &mut f32,
+        travel: f32,
+        forward: bool,
+        cycles: &mut u32,
+    ) -> Advance {
+        advance(
+            &lengths(),
+            &WAYPOINTS,
+            |waypoint| waits[waypoint],
+            mode,
+            distance,
+            travel,
+            forward,
+            cycles,
+        )
+    }
+
+    fn reached(advance: &Advance) -> Vec<(usize, bool)> {
+        advance
+            .reached
+            .iter()
+            .map(|reached| (reached.waypoint, reached.completed_loop))
+            .collect()
+    }
+
+    #[test]
+    fn once_finishes_at_the_end() {
+        let (mut distance, mut cycles) = (0., 0);
+        let advance = run(
+            PathMode::Once,
+            [0.; 3],
+            &mut distance,
+            15.,
+            true,
+            &mut cycles,
+        );
+        assert_eq!((distance, cycles, advance.finished), (15., 0, false));
+        assert_eq!(reached(&advance), [(1, false)]);
+
+        let advance = run(
+            PathMode::Once,
+            [0.; 3],
+            &mut distance,
+            15.,
+            true,
+            &mut cycles,
+        );
+        assert_eq!((distance, cycles, advance.finished), (20., 1, true));
+        assert_eq!(reached(&advance), [(2, false)]);
+
+        // Finishing happens once
+        let advance = run(
+            PathMode::Once,
+            [0.; 3],
+            &mut distance,
+            5.,
+            true,
+            &mut cycles,
+        );
+        assert_eq!((distance, cycles, advance.finished), (20., 1, false));
+    }
+
+    #[test]
+    fn once_finishes_when_standing_at_the_end() {
+        let (mut distance, mut cycles) = (20., 0);
+        let advance = run(
+            PathMode::Once,
+            [0.; 3],
+            &mut distance,
+            0.,
+            true,
+            &mut cycles,
+        );
+        assert_eq!((distance, cycles, advance.finished), (20., 1, true));
+
+        // Standing at the end while moving backward is the start of the path
+        let (mut distance, mut cycles) = (20., 0);
+        let advance = run(
+            PathMode::Once,
+            [0.; 3],
+            &mut distance,
+            0.,
+            false,
+            &mut cycles,
+        );
+        assert_eq!((distance, cycles, advance.finished), (20., 0, false));
+    }
+
+    #[test]
+    fn loop_wraps_around() {
+        let (mut distance, mut cycles) = (35., 0);
+        let advance = run(
+            PathMode::Loop,
+            [0.; 3],
+            &mut distance,
+            10.,
+            true,
+            &mut cycles,
+        );
+        assert_eq!((distance, cycles, advance.finished), (5., 1, false));
+        assert_eq!(reached(&advance), [(0, true)]);
+
+        let advance = run(
+            PathMode::Loop,
+            [0.; 3],
+            &mut distance,
+            10.,
+            false,
+            &mut cycles,
+        );
+        assert_eq!((distance, cycles, advance.finished), (35., 2, false));
+        assert_eq!(reached(&advance), [(0, true)]);
+    }
+
+    #[test]
+    fn ping_pong_bounces_off_both_ends() {
+        let (mut distance, mut cycles) = (15., 0);
+        let advance = run(
+            PathMode::PingPong,
+            [0.; 3],
+            &mut distance,
+            10.,
+            true,
+            &mut cycles,
+        );
+        assert_eq!((distance, cycles, advance.turned), (15., 0, true));
+        assert_eq!(reached(&advance), [(2, false)]);
+
+        // Arriving back at the start completes a cycle
+        let advance = run(
+            PathMode::PingPong,
+            [0.; 3],
+            &mut distance,
+            20.,
+            false,
+            &mut cycles,
+        );
+        assert_eq!((distance, cycles, advance.turned), (5., 1, true));
+        assert_eq!(reached(&advance), [(1, false), (0, true)]);
+        assert!(!advance.finished);
+    }
+
+    #[test]
+    fn repeat_finishes_after_its_cycles() {
+        let (mut distance, mut cycles) = (0., 0);
+        let advance = run(
+            PathMode::Repeat(2),
+            [0.; 3],
+            &mut distance,
+            100.,
+            true,
+            &mut cycles,
+        );
+        assert_eq!((distance, cycles, advance.finished), (40., 2, true));
+        assert_eq!(
+            reached(&advance),
+            [
+                (1, false),
+                (2, false),
+                (0, true),
+                (1, false),
+                (2, false),
+                (0, true)
+            ]
+        );
+
+        let advance = run(
+            PathMode::Repeat(2),
+            [0.; 3],
+            &mut distance,
+            10.,
+            true,
+            &mut cycles,
+        );
+        assert_eq!((cycles, advance.finished), (2, false));
+    }
+
+    #[test]
+    fn backward_travel_stops_at_waiting_waypoints() {
+        let waits = [0., 2., 0.];
+        let (mut distance, mut cycles) = (20., 0);
+        let advance = run(
+            PathMode::Once,
+            waits,
+            &mut distance,
+            15.,
+            false,
+            &mut cycles,
+        );
+        assert_eq!((distance, advance.finished), (10., false));
+        assert_eq!(reached(&advance), [(1, false)]);
+        assert_eq!(
+            advance.dwell,
+            Some(Dwell {
+                waypoint: 1,
+                remaining: 2.
+            })
+        );
+
+        // After waiting, the follower carries on to the start
+        let advance = run(
+            PathMode::Once,
+            waits,
+            &mut distance,
+            15.,
+            false,
+            &mut cycles,
+        );
+        assert_eq!((distance, cycles, advance.finished), (0., 1, true));
+        assert_eq!(reached(&advance), [(0, false)]);
+    }
+
+    #[test]
+    fn distance_to_stop_finds_the_next_waiting_waypoint() {
+        let stop = |mode, distance, forward| {
+            distance_to_stop(&lengths(), &WAYPOINTS, &[1], mode, distance, forward, 0)
+        };
+        assert_eq!(stop(PathMode::Once, 0., true), 10.);
+        assert_eq!(stop(PathMode::Once, 20., false), 10.);
+        assert_eq!(stop(PathMode::Once, 10., false), 10.);
+        assert_eq!(stop(PathMode::Once, 15., true), 5.);
+        // The waypoint waited at lies a full lap ahead on loops
+        assert_eq!(stop(PathMode::Loop, 10., true), 40.);
+        assert_eq!(stop(PathMode::Loop, 5., false), 35.);
+        assert_eq!(stop(PathMode::Repeat(1), 15., true), 25.);
+    }
+}